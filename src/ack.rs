@@ -0,0 +1,164 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{EncodeBody, Error, PacketId, PacketTypeFlags, Result, Status};
+
+/// Defines a packet whose variable header is just a packet identifier.
+macro_rules! ack_packet {
+    ($(#[$attr:meta])* $name:ident, $fixed_header_byte:expr) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, PartialEq, Eq, Debug)]
+        pub struct $name {
+            packet_id: PacketId,
+        }
+
+        impl $name {
+            pub fn new(packet_id: PacketId) -> $name {
+                $name { packet_id }
+            }
+
+            pub fn from_bytes(bytes: &[u8]) -> Result<Status<$name>> {
+                // read packet identifier
+                let read = 0;
+                let packet_id = read_u16!(bytes, read);
+
+                Ok(Status::Complete($name { packet_id }))
+            }
+
+            pub fn packet_id(&self) -> PacketId {
+                self.packet_id
+            }
+        }
+
+        impl EncodeBody for $name {
+            fn fixed_header_byte(&self) -> u8 {
+                $fixed_header_byte
+            }
+
+            fn body_len(&self) -> usize {
+                2
+            }
+
+            fn encode_body(&self, buf: &mut [u8]) {
+                BigEndian::write_u16(buf, self.packet_id);
+            }
+        }
+    };
+}
+
+ack_packet!(
+    /// Publish acknowledgement, the response to a QoS 1 PUBLISH.
+    Puback,
+    4 << 4
+);
+ack_packet!(
+    /// Publish received, the first response to a QoS 2 PUBLISH.
+    Pubrec,
+    5 << 4
+);
+ack_packet!(
+    /// Publish release, the response to a PUBREC.
+    Pubrel,
+    6 << 4 | 0b0010
+);
+ack_packet!(
+    /// Publish complete, the response to a PUBREL.
+    Pubcomp,
+    7 << 4
+);
+ack_packet!(
+    /// Unsubscribe acknowledgement, the response to an UNSUBSCRIBE.
+    Unsuback,
+    11 << 4
+);
+
+impl Pubrel {
+    /// Checks the fixed header flags of a PUBREL, which MUST be `0b0010`
+    /// (MQTT-3.6.1-1).
+    pub fn validate_flags(flags: PacketTypeFlags) -> Result<()> {
+        if flags != 0b0010 {
+            return Err(Error::PacketFlag);
+        }
+
+        Ok(())
+    }
+}
+
+impl Puback {
+    /// Returns `true` when the PUBLISH was accepted. A 3.1.1 PUBACK carries
+    /// no reason code and is only sent on success, so this always holds.
+    pub fn is_success(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encode;
+
+    #[test]
+    fn packet_id() {
+        let buf = [0x12, 0x34];
+        assert_eq!(
+            Puback::from_bytes(&buf).unwrap().unwrap().packet_id(),
+            0x1234
+        );
+        assert_eq!(
+            Pubrec::from_bytes(&buf).unwrap().unwrap().packet_id(),
+            0x1234
+        );
+        assert_eq!(
+            Pubrel::from_bytes(&buf).unwrap().unwrap().packet_id(),
+            0x1234
+        );
+        assert_eq!(
+            Pubcomp::from_bytes(&buf).unwrap().unwrap().packet_id(),
+            0x1234
+        );
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0u8; 4];
+        assert_eq!(Puback::new(0x1234).encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x40, 2, 0x12, 0x34]);
+        assert_eq!(Pubrec::new(1).encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x50, 2, 0, 1]);
+        assert_eq!(Pubrel::new(1).encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x62, 2, 0, 1]);
+        assert_eq!(Pubcomp::new(1).encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x70, 2, 0, 1]);
+        assert_eq!(Unsuback::new(1).encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0xB0, 2, 0, 1]);
+        assert_eq!(
+            Puback::new(1).encode(&mut buf[..3]),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn partial() {
+        for len in 0..2 {
+            let buf = &[0x12, 0x34][..len];
+            assert_eq!(Puback::from_bytes(buf).unwrap(), Status::Partial);
+            assert_eq!(Pubrec::from_bytes(buf).unwrap(), Status::Partial);
+            assert_eq!(Pubrel::from_bytes(buf).unwrap(), Status::Partial);
+            assert_eq!(Pubcomp::from_bytes(buf).unwrap(), Status::Partial);
+            assert_eq!(Unsuback::from_bytes(buf).unwrap(), Status::Partial);
+        }
+    }
+
+    #[test]
+    fn puback_is_success() {
+        let puback = Puback::from_bytes(&[0, 1]).unwrap().unwrap();
+        assert!(puback.is_success());
+    }
+
+    #[test]
+    fn pubrel_flags() {
+        assert_eq!(Pubrel::validate_flags(0b0010), Ok(()));
+        for flags in [0b0000, 0b0001, 0b0011, 0b1010].iter() {
+            assert_eq!(Pubrel::validate_flags(*flags), Err(Error::PacketFlag));
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use super::{EncodeBody, Error, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConnackReturnCode {
+    /// Connection accepted
+    Accepted,
+    /// The server does not support the requested protocol level
+    UnacceptableProtocol,
+    /// The client identifier is not allowed by the server
+    IdentifierRejected,
+    /// The MQTT service is unavailable
+    ServerUnavailable,
+    /// The user name or password is malformed
+    BadCredentials,
+    /// The client is not authorized to connect
+    NotAuthorized,
+}
+
+impl ConnackReturnCode {
+    pub fn from_u8(value: u8) -> Result<ConnackReturnCode> {
+        match value {
+            0 => Ok(ConnackReturnCode::Accepted),
+            1 => Ok(ConnackReturnCode::UnacceptableProtocol),
+            2 => Ok(ConnackReturnCode::IdentifierRejected),
+            3 => Ok(ConnackReturnCode::ServerUnavailable),
+            4 => Ok(ConnackReturnCode::BadCredentials),
+            5 => Ok(ConnackReturnCode::NotAuthorized),
+            _ => Err(Error::InvalidConnackReturnCode),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            ConnackReturnCode::Accepted => 0,
+            ConnackReturnCode::UnacceptableProtocol => 1,
+            ConnackReturnCode::IdentifierRejected => 2,
+            ConnackReturnCode::ServerUnavailable => 3,
+            ConnackReturnCode::BadCredentials => 4,
+            ConnackReturnCode::NotAuthorized => 5,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Connack {
+    session_present: bool,
+    return_code: ConnackReturnCode,
+}
+
+impl Connack {
+    pub fn new(session_present: bool, return_code: ConnackReturnCode) -> Connack {
+        Connack {
+            session_present,
+            return_code,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Status<Connack>> {
+        // read connect acknowledge flags
+        let mut read = 0;
+        let flags = next!(bytes, read);
+        read += 1;
+
+        // bits 7-1 are reserved and MUST be set to 0
+        if flags & 0b1111_1110 != 0 {
+            return Err(Error::InvalidConnackFlag);
+        }
+
+        // read connect return code
+        let return_code = ConnackReturnCode::from_u8(next!(bytes, read))?;
+
+        Ok(Status::Complete(Connack {
+            session_present: flags & 0b0000_0001 != 0,
+            return_code,
+        }))
+    }
+
+    pub fn session_present(&self) -> bool {
+        self.session_present
+    }
+
+    pub fn return_code(&self) -> ConnackReturnCode {
+        self.return_code
+    }
+}
+
+impl EncodeBody for Connack {
+    fn fixed_header_byte(&self) -> u8 {
+        2 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        2
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        buf[0] = self.session_present as u8;
+        buf[1] = self.return_code.to_u8();
+    }
+
+    fn validate(&self) -> Result<()> {
+        // a refused connection MUST NOT have the session present flag set
+        // (MQTT-3.2.2-4)
+        if self.session_present && self.return_code != ConnackReturnCode::Accepted {
+            return Err(Error::InvalidConnackFlag);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encode;
+
+    #[test]
+    fn connack() {
+        let connack = Connack::from_bytes(&[0b0000_0001, 0]).unwrap().unwrap();
+        assert!(connack.session_present());
+        assert_eq!(connack.return_code(), ConnackReturnCode::Accepted);
+
+        let connack = Connack::from_bytes(&[0b0000_0000, 5]).unwrap().unwrap();
+        assert!(!connack.session_present());
+        assert_eq!(connack.return_code(), ConnackReturnCode::NotAuthorized);
+    }
+
+    #[test]
+    fn partial() {
+        assert_eq!(Connack::from_bytes(&[]).unwrap(), Status::Partial);
+        assert_eq!(Connack::from_bytes(&[0]).unwrap(), Status::Partial);
+    }
+
+    #[test]
+    fn reserved_flags() {
+        for i in 1..8 {
+            let result = Connack::from_bytes(&[1 << i, 0]);
+            assert_eq!(result, Err(Error::InvalidConnackFlag));
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let mut buf = [0u8; 4];
+        let connack = Connack::new(true, ConnackReturnCode::Accepted);
+        assert_eq!(connack.encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x20, 2, 1, 0]);
+
+        let connack = Connack::new(false, ConnackReturnCode::NotAuthorized);
+        assert_eq!(connack.encode(&mut buf), Ok(4));
+        assert_eq!(buf, [0x20, 2, 0, 5]);
+
+        let connack = Connack::new(true, ConnackReturnCode::NotAuthorized);
+        assert_eq!(connack.encode(&mut buf), Err(Error::InvalidConnackFlag));
+    }
+
+    #[test]
+    fn return_codes() {
+        let codes = [
+            ConnackReturnCode::Accepted,
+            ConnackReturnCode::UnacceptableProtocol,
+            ConnackReturnCode::IdentifierRejected,
+            ConnackReturnCode::ServerUnavailable,
+            ConnackReturnCode::BadCredentials,
+            ConnackReturnCode::NotAuthorized,
+        ];
+        for (i, code) in codes.iter().enumerate() {
+            assert_eq!(ConnackReturnCode::from_u8(i as u8), Ok(*code));
+        }
+        assert_eq!(
+            Connack::from_bytes(&[0, 6]),
+            Err(Error::InvalidConnackReturnCode)
+        );
+    }
+}
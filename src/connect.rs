@@ -1,13 +1,35 @@
-use super::{parse_string, Result, Status};
+use core::time::Duration;
 
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{
+    parse, parse_bytes, parse_string, Connack, ConnackReturnCode, Encode, EncodeBody, Error,
+    FixedHeader, Packet, PacketType, ProtocolVersion, QoS, Result, Status,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub struct Connect<'buf> {
     name: &'buf str,
     revision: u8,
     flags: u8,
+    will_qos: QoS,
+    keep_alive: u16,
+    client_id: &'buf str,
+    will_topic: Option<&'buf str>,
+    will_message: Option<&'buf [u8]>,
+    username: Option<&'buf str>,
+    password: Option<&'buf [u8]>,
 }
 
 impl<'buf> Connect<'buf> {
-    pub fn from_bytes(bytes: &[u8]) -> Result<Status<Connect>> {
+    pub fn from_bytes(bytes: &'buf [u8]) -> Result<Status<Connect<'buf>>> {
+        let (connect, _) = complete!(Connect::from_bytes_consumed(bytes));
+        Ok(Status::Complete(connect))
+    }
+
+    /// Same as `from_bytes` but also returns the number of bytes consumed
+    /// from `bytes`.
+    pub fn from_bytes_consumed(bytes: &'buf [u8]) -> Result<Status<(Connect<'buf>, usize)>> {
         // read protocol name
         let name = complete!(parse_string(bytes));
         let mut read = 2 + name.len(); // 2 bytes for the string len prefix + length of string in bytes
@@ -18,12 +40,100 @@ impl<'buf> Connect<'buf> {
 
         // read protocol flags
         let flags = next!(bytes, read);
+        read += 1;
+        let will_qos = QoS::from_u8((flags & 0b0001_1000) >> 3)?;
+
+        // read keep alive
+        let keep_alive = read_u16!(bytes, read);
+        read += 2;
 
-        Ok(Status::Complete(Connect {
+        let mut connect = Connect {
             name,
             revision,
             flags,
-        }))
+            will_qos,
+            keep_alive,
+            client_id: "",
+            will_topic: None,
+            will_message: None,
+            username: None,
+            password: None,
+        };
+
+        // read payload; the fields are present in this order depending on
+        // the connect flags
+        connect.client_id = complete!(parse_string(&bytes[read..]));
+        read += 2 + connect.client_id.len();
+
+        if connect.will_flag() {
+            let will_topic = complete!(parse_string(&bytes[read..]));
+            read += 2 + will_topic.len();
+            let will_message = complete!(parse_bytes(&bytes[read..]));
+            read += 2 + will_message.len();
+
+            connect.will_topic = Some(will_topic);
+            connect.will_message = Some(will_message);
+        }
+
+        if connect.username_present() {
+            let username = complete!(parse_string(&bytes[read..]));
+            read += 2 + username.len();
+            connect.username = Some(username);
+        }
+
+        if connect.password_present() {
+            let password = complete!(parse_bytes(&bytes[read..]));
+            read += 2 + password.len();
+            connect.password = Some(password);
+        }
+
+        Ok(Status::Complete((connect, read)))
+    }
+
+    /// Same as `from_bytes` but also checks the CONNECT against the rules
+    /// `from_bytes` leaves to the caller.
+    ///
+    /// A MQTT 3.1.1 CONNECT must use the protocol name "MQTT" (MQTT-3.1.2-1),
+    /// otherwise `Error::InvalidProtocolName` is returned. The reserved bit 0
+    /// of the connect flags must be zero (MQTT-3.1.2-3), otherwise
+    /// `Error::InvalidConnectFlag` is returned. A zero length client
+    /// identifier is only allowed along with clean session (MQTT-3.1.3-7,
+    /// MQTT-3.1.3-8) and results in `Error::EmptyClientId` otherwise.
+    pub fn from_bytes_validated(bytes: &'buf [u8]) -> Result<Status<Connect<'buf>>> {
+        let connect = complete!(Connect::from_bytes(bytes));
+        connect.check_validated()?;
+
+        Ok(Status::Complete(connect))
+    }
+
+    /// The checks of `from_bytes_validated`, shared with `Packet::validate`.
+    pub(crate) fn check_validated(&self) -> Result<()> {
+        if self.protocol_version() == ProtocolVersion::V3_1_1 && self.name != "MQTT" {
+            return Err(Error::InvalidProtocolName);
+        }
+
+        if self.flags & 0b0000_0001 != 0 {
+            return Err(Error::InvalidConnectFlag);
+        }
+
+        if self.client_id.is_empty() && !self.clean_session() {
+            return Err(Error::EmptyClientId);
+        }
+
+        Ok(())
+    }
+
+    /// Reads just the keep alive (in seconds) from the CONNECT variable
+    /// header without decoding the payload.
+    pub fn peek_keepalive(bytes: &[u8]) -> Result<Status<u16>> {
+        let name = complete!(parse_string(bytes));
+        // skip protocol name, revision and flags
+        let read = 2 + name.len() + 2;
+        if bytes.len() < read {
+            return Ok(Status::Partial);
+        }
+
+        Ok(Status::Complete(read_u16!(bytes, read)))
     }
 
     pub fn name(&self) -> &str {
@@ -34,7 +144,929 @@ impl<'buf> Connect<'buf> {
         &self.revision
     }
 
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        ProtocolVersion::from_u8(self.revision)
+    }
+
     pub fn flags(&self) -> &u8 {
         &self.flags
     }
+
+    pub fn keep_alive(&self) -> &u16 {
+        &self.keep_alive
+    }
+
+    pub fn client_id(&self) -> &str {
+        self.client_id
+    }
+
+    pub fn will_topic(&self) -> Option<&str> {
+        self.will_topic
+    }
+
+    pub fn will_message(&self) -> Option<&[u8]> {
+        self.will_message
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username
+    }
+
+    pub fn password(&self) -> Option<&[u8]> {
+        self.password
+    }
+
+    /// Splits the user name at the first `sep`, e.g. into tenant and user for
+    /// a `tenant:user` convention. Returns `None` without a user name or when
+    /// it does not contain `sep`.
+    pub fn username_split(&self, sep: char) -> Option<(&str, &str)> {
+        let username = self.username?;
+        let index = username.find(sep)?;
+        Some((&username[..index], &username[index + sep.len_utf8()..]))
+    }
+
+    pub fn clean_session(&self) -> bool {
+        self.flags & 0b0000_0010 != 0
+    }
+
+    pub fn will_flag(&self) -> bool {
+        self.flags & 0b0000_0100 != 0
+    }
+
+    pub fn will_qos(&self) -> QoS {
+        self.will_qos
+    }
+
+    pub fn will_retain(&self) -> bool {
+        self.flags & 0b0010_0000 != 0
+    }
+
+    pub fn password_present(&self) -> bool {
+        self.flags & 0b0100_0000 != 0
+    }
+
+    pub fn username_present(&self) -> bool {
+        self.flags & 0b1000_0000 != 0
+    }
+
+    /// Returns `true` when the protocol level of this CONNECT is one of
+    /// `supported`.
+    pub fn is_supported_version(&self, supported: &[u8]) -> bool {
+        supported.contains(&self.revision)
+    }
+
+    /// Returns the CONNACK rejecting this CONNECT when its protocol level is
+    /// not one of `supported`, or `None` when the version is acceptable.
+    pub fn version_rejection(&self, supported: &[u8]) -> Option<Connack> {
+        if self.is_supported_version(supported) {
+            None
+        } else {
+            Some(Connack::new(false, ConnackReturnCode::UnacceptableProtocol))
+        }
+    }
+
+    /// Returns the CONNACK accepting this CONNECT, for brokers without
+    /// custom connection handling.
+    ///
+    /// Session present is set unless clean session is requested
+    /// (MQTT-3.2.2-1), i.e. this assumes the broker keeps a session for every
+    /// client that asks for one.
+    pub fn default_connack(&self) -> Connack {
+        Connack::new(!self.clean_session(), ConnackReturnCode::Accepted)
+    }
+
+    /// Assembles the connect flags byte from the current field values, as it
+    /// is emitted by `to_bytes`.
+    ///
+    /// The will, user name and password bits follow the presence of the
+    /// corresponding payload fields rather than the decoded flags. The will
+    /// QoS and will retain bits are only set along with a will, and the
+    /// reserved bit 0 is always zero.
+    pub fn computed_flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.clean_session() {
+            flags |= 0b0000_0010;
+        }
+        if self.has_will() {
+            flags |= 0b0000_0100 | self.will_qos.to_u8() << 3;
+            if self.will_retain() {
+                flags |= 0b0010_0000;
+            }
+        }
+        if self.password.is_some() {
+            flags |= 0b0100_0000;
+        }
+        if self.username.is_some() {
+            flags |= 0b1000_0000;
+        }
+        flags
+    }
+
+    /// Returns the connect flags byte this CONNECT would be encoded with,
+    /// see `computed_flags`.
+    pub fn preview_flags(&self) -> u8 {
+        self.computed_flags()
+    }
+
+    fn has_will(&self) -> bool {
+        self.will_topic.is_some() || self.will_message.is_some()
+    }
+
+    /// Checks that the connect flags and fields agree with each other.
+    ///
+    /// A password requires a user name (MQTT-3.1.2-22), and the will QoS,
+    /// will retain and will topic/message require the will flag
+    /// (MQTT-3.1.2-11, MQTT-3.1.2-13, MQTT-3.1.2-15).
+    pub fn check_consistency(&self) -> Result<()> {
+        if self.password_present() && !self.username_present() {
+            return Err(Error::PasswordWithoutUsername);
+        }
+
+        if !self.will_flag()
+            && (self.flags & 0b0011_1000 != 0
+                || self.will_topic.is_some()
+                || self.will_message.is_some())
+        {
+            return Err(Error::WillFieldWithoutWillFlag);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the will QoS against the maximum QoS supported by the server,
+    /// returning `Error::QoSExceedsMaximum` when a will asks for more.
+    ///
+    /// Without a will the will QoS is zero, so only CONNECTs with the will
+    /// flag set can be rejected.
+    pub fn validate_will_qos_against_max(&self, max: QoS) -> Result<()> {
+        if self.will_flag() && self.will_qos.to_u8() > max.to_u8() {
+            return Err(Error::QoSExceedsMaximum);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the encoded size of the CONNECT payload: the client id plus the
+    /// optional will topic and message, user name and password, each with
+    /// its 2 byte length prefix.
+    pub fn payload_len(&self) -> usize {
+        let mut len = 2 + self.client_id.len();
+        if self.has_will() {
+            len += 2 + self.will_topic.map_or(0, str::len);
+            len += 2 + self.will_message.map_or(0, <[u8]>::len);
+        }
+        if let Some(username) = self.username {
+            len += 2 + username.len();
+        }
+        if let Some(password) = self.password {
+            len += 2 + password.len();
+        }
+        len
+    }
+
+    /// Returns `true` when the client asks the server to keep session state
+    /// across network connections.
+    ///
+    /// In MQTT 3.1.1 this is the case when the clean session flag is not set.
+    /// The MQTT 5.0 Session Expiry Interval lives in the CONNECT properties
+    /// which are not decoded by this crate, so only the flag is considered.
+    pub fn wants_persistent_session(&self) -> bool {
+        !self.clean_session()
+    }
+
+    /// Returns how often a client should send a PINGREQ so the keep alive
+    /// does not expire while the ping is in flight: 80% of the keep alive,
+    /// or `None` when the keep alive mechanism is disabled.
+    pub fn recommended_ping_interval(&self) -> Option<Duration> {
+        if self.keep_alive == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(u64::from(self.keep_alive) * 800))
+        }
+    }
+
+    /// Returns a copy of this CONNECT with `username` set as the user name.
+    ///
+    /// This is meant for proxies that authenticate the client by other means
+    /// (e.g. the common name of a TLS client certificate) and forward the
+    /// resulting identity to the server.
+    pub fn with_injected_username(&self, username: &'buf str) -> Connect<'buf> {
+        self.with_username(username)
+    }
+
+    /// Creates a MQTT 3.1.1 CONNECT for `client_id` with clean session set,
+    /// keep alive disabled and no will or credentials, to be completed with
+    /// the `with_*` methods.
+    pub fn new(client_id: &'buf str) -> Connect<'buf> {
+        Connect {
+            name: "MQTT",
+            revision: ProtocolVersion::V3_1_1.to_u8(),
+            flags: 0b0000_0010,
+            will_qos: QoS::AtMostOnce,
+            keep_alive: 0,
+            client_id,
+            will_topic: None,
+            will_message: None,
+            username: None,
+            password: None,
+        }
+    }
+
+    pub fn with_clean_session(&self, clean_session: bool) -> Connect<'buf> {
+        Connect {
+            flags: set_flag(self.flags, 0b0000_0010, clean_session),
+            ..*self
+        }
+    }
+
+    pub fn with_keep_alive(&self, keep_alive: u16) -> Connect<'buf> {
+        Connect {
+            keep_alive,
+            ..*self
+        }
+    }
+
+    /// Returns a copy of this CONNECT with `topic` as the will topic, which
+    /// also sets the will flag.
+    pub fn with_will_topic(&self, topic: &'buf str) -> Connect<'buf> {
+        Connect {
+            flags: self.flags | 0b0000_0100,
+            will_topic: Some(topic),
+            ..*self
+        }
+    }
+
+    /// Returns a copy of this CONNECT with `message` as the will message,
+    /// which also sets the will flag.
+    pub fn with_will_msg(&self, message: &'buf [u8]) -> Connect<'buf> {
+        Connect {
+            flags: self.flags | 0b0000_0100,
+            will_message: Some(message),
+            ..*self
+        }
+    }
+
+    pub fn with_will_qos(&self, will_qos: QoS) -> Connect<'buf> {
+        Connect {
+            flags: self.flags & !0b0001_1000 | will_qos.to_u8() << 3,
+            will_qos,
+            ..*self
+        }
+    }
+
+    pub fn with_will_retain(&self, will_retain: bool) -> Connect<'buf> {
+        Connect {
+            flags: set_flag(self.flags, 0b0010_0000, will_retain),
+            ..*self
+        }
+    }
+
+    /// Returns a copy of this CONNECT without a will, clearing the will flag,
+    /// will QoS and will retain bits along with the will topic and message
+    /// so the result satisfies MQTT-3.1.2-11.
+    ///
+    /// This is meant for proxies that drop the client's will before
+    /// forwarding the CONNECT.
+    pub fn without_will(&self) -> Connect<'buf> {
+        Connect {
+            flags: self.flags & !0b0011_1100,
+            will_qos: QoS::AtMostOnce,
+            will_topic: None,
+            will_message: None,
+            ..*self
+        }
+    }
+
+    pub fn with_username(&self, username: &'buf str) -> Connect<'buf> {
+        Connect {
+            flags: self.flags | 0b1000_0000,
+            username: Some(username),
+            ..*self
+        }
+    }
+
+    pub fn with_password(&self, password: &'buf [u8]) -> Connect<'buf> {
+        Connect {
+            flags: self.flags | 0b0100_0000,
+            password: Some(password),
+            ..*self
+        }
+    }
+
+    /// Encodes this CONNECT, including the fixed header, into `buf` and
+    /// returns the number of bytes written.
+    ///
+    /// The connect flags byte is assembled by `computed_flags` from the fields
+    /// actually present rather than copied from the decoded flags. A CONNECT
+    /// failing `check_consistency` is rejected with the corresponding error.
+    /// A field longer than 65535 bytes or a buffer too small for the packet
+    /// results in `Error::InvalidLength`.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize> {
+        self.encode(buf)
+    }
+}
+
+impl<'buf> EncodeBody for Connect<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        1 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        // protocol name, revision, flags and keep alive
+        2 + self.name.len() + 4 + self.payload_len()
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        let mut write = write_prefixed(buf, self.name.as_bytes());
+        buf[write] = self.revision;
+        buf[write + 1] = self.computed_flags();
+        BigEndian::write_u16(&mut buf[write + 2..], self.keep_alive);
+        write += 4;
+
+        write += write_prefixed(&mut buf[write..], self.client_id.as_bytes());
+        if self.has_will() {
+            let topic = self.will_topic.unwrap_or("");
+            write += write_prefixed(&mut buf[write..], topic.as_bytes());
+            let message = self.will_message.unwrap_or(&[]);
+            write += write_prefixed(&mut buf[write..], message);
+        }
+        if let Some(username) = self.username {
+            write += write_prefixed(&mut buf[write..], username.as_bytes());
+        }
+        if let Some(password) = self.password {
+            write_prefixed(&mut buf[write..], password);
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.check_consistency()?;
+
+        // every string and binary field carries a 2 byte length prefix
+        let fields = [
+            Some(self.name.as_bytes()),
+            Some(self.client_id.as_bytes()),
+            self.will_topic.map(str::as_bytes),
+            self.will_message,
+            self.username.map(str::as_bytes),
+            self.password,
+        ];
+        if fields
+            .iter()
+            .flatten()
+            .any(|field| field.len() > u16::MAX as usize)
+        {
+            return Err(Error::InvalidLength);
+        }
+
+        Ok(())
+    }
+}
+
+fn set_flag(flags: u8, mask: u8, value: bool) -> u8 {
+    if value {
+        flags | mask
+    } else {
+        flags & !mask
+    }
+}
+
+fn write_prefixed(buf: &mut [u8], field: &[u8]) -> usize {
+    BigEndian::write_u16(buf, field.len() as u16);
+    buf[2..2 + field.len()].copy_from_slice(field);
+    2 + field.len()
+}
+
+/// Parses the first packet received on a connection, which must be a CONNECT
+/// (MQTT-3.1.0-1).
+///
+/// `bytes` starts with the fixed header. Any other packet type results in
+/// `Error::FirstPacketNotConnect`, and a CONNECT which does not fill its
+/// remaining length exactly in `Error::InvalidLength`, as with `parse`.
+pub fn expect_connect(bytes: &[u8]) -> Result<Status<Connect<'_>>> {
+    let (header, _) = complete!(FixedHeader::from_bytes(bytes));
+    if header.packet_type() != PacketType::Connect {
+        return Err(Error::FirstPacketNotConnect);
+    }
+
+    match complete!(parse(bytes)) {
+        (Packet::Connect(connect), _) => Ok(Status::Complete(connect)),
+        _ => Err(Error::FirstPacketNotConnect),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::Encoded;
+
+    fn connect_bytes(flags: u8, payload: &[&[u8]]) -> Encoded {
+        let mut buf = Encoded::new();
+        buf.push_prefixed(b"MQTT");
+        buf.push(&[4, flags]);
+        buf.push_u16(60);
+        for field in payload {
+            buf.push_prefixed(field);
+        }
+        buf
+    }
+
+    #[test]
+    fn connect() {
+        let buf = connect_bytes(
+            0b1110_0110,
+            &[b"client", b"will/topic", b"bye", b"user", b"pass"],
+        );
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.name(), "MQTT");
+        assert_eq!(*connect.revision(), 4);
+        assert_eq!(connect.protocol_version(), ProtocolVersion::V3_1_1);
+        assert_eq!(*connect.keep_alive(), 60);
+        assert_eq!(connect.client_id(), "client");
+        assert_eq!(connect.will_topic(), Some("will/topic"));
+        assert_eq!(connect.will_message(), Some(&b"bye"[..]));
+        assert!(connect.will_retain());
+        assert_eq!(connect.username(), Some("user"));
+        assert_eq!(connect.password(), Some(&b"pass"[..]));
+    }
+
+    #[test]
+    fn payload_len() {
+        let buf = connect_bytes(
+            0b1110_0110,
+            &[b"client", b"will/topic", b"bye", b"user", b"pass"],
+        );
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        // protocol name, revision, flags and keep alive
+        let variable_header_len = 2 + 4 + 1 + 1 + 2;
+        assert_eq!(connect.payload_len(), buf.len() - variable_header_len);
+
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.payload_len(), 2 + 6);
+    }
+
+    #[test]
+    fn peek_keepalive() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        assert_eq!(Connect::peek_keepalive(&buf).unwrap(), Status::Complete(60));
+        // only the variable header is needed
+        assert_eq!(
+            Connect::peek_keepalive(&buf[..10]).unwrap(),
+            Status::Complete(60)
+        );
+        for len in 0..10 {
+            let result = Connect::peek_keepalive(&buf[..len]).unwrap();
+            assert_eq!(result, Status::Partial);
+        }
+    }
+
+    #[test]
+    fn consistent() {
+        let buf = connect_bytes(
+            0b1110_0110,
+            &[b"client", b"will/topic", b"bye", b"user", b"pass"],
+        );
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.check_consistency(), Ok(()));
+        let connect = connect.with_injected_username("device-cn");
+        assert_eq!(connect.check_consistency(), Ok(()));
+    }
+
+    #[test]
+    fn password_without_username() {
+        let buf = connect_bytes(0b0100_0010, &[b"client", b"pass"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.check_consistency(),
+            Err(Error::PasswordWithoutUsername)
+        );
+    }
+
+    #[test]
+    fn will_qos_without_will_flag() {
+        let buf = connect_bytes(0b0000_1010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.check_consistency(),
+            Err(Error::WillFieldWithoutWillFlag)
+        );
+    }
+
+    #[test]
+    fn will_retain_without_will_flag() {
+        let buf = connect_bytes(0b0010_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.check_consistency(),
+            Err(Error::WillFieldWithoutWillFlag)
+        );
+    }
+
+    #[test]
+    fn protocol_version() {
+        let inputs = [
+            (3, ProtocolVersion::V3_1),
+            (4, ProtocolVersion::V3_1_1),
+            (5, ProtocolVersion::V5),
+            (6, ProtocolVersion::Unknown(6)),
+        ];
+        for &(revision, version) in inputs.iter() {
+            let mut buf = connect_bytes(0b0000_0010, &[b"client"]);
+            buf[6] = revision;
+            let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+            assert_eq!(*connect.revision(), revision);
+            assert_eq!(connect.protocol_version(), version);
+        }
+    }
+
+    #[test]
+    fn ping_interval() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.recommended_ping_interval(),
+            Some(Duration::from_secs(48))
+        );
+
+        let mut buf = connect_bytes(0b0000_0010, &[b"client"]);
+        buf[8] = 0;
+        buf[9] = 0;
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(*connect.keep_alive(), 0);
+        assert_eq!(connect.recommended_ping_interval(), None);
+    }
+
+    #[test]
+    fn flags() {
+        let buf = connect_bytes(0b1100_0010, &[b"client", b"user", b"pass"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(connect.clean_session());
+        assert!(connect.username_present());
+        assert!(connect.password_present());
+        assert!(!connect.will_flag());
+        assert!(!connect.will_retain());
+    }
+
+    #[test]
+    fn will_qos() {
+        let buf = connect_bytes(0b0001_0110, &[b"client", b"will/topic", b"bye"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(connect.will_flag());
+        assert_eq!(connect.will_qos(), QoS::ExactlyOnce);
+
+        let buf = connect_bytes(0b0000_1110, &[b"client", b"will/topic", b"bye"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.will_qos(), QoS::AtLeastOnce);
+    }
+
+    #[test]
+    fn will_qos_against_max() {
+        let buf = connect_bytes(0b0001_0110, &[b"client", b"will/topic", b"bye"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.validate_will_qos_against_max(QoS::AtLeastOnce),
+            Err(Error::QoSExceedsMaximum)
+        );
+        assert_eq!(
+            connect.validate_will_qos_against_max(QoS::ExactlyOnce),
+            Ok(())
+        );
+
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(
+            connect.validate_will_qos_against_max(QoS::AtMostOnce),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn bad_will_qos() {
+        let buf = connect_bytes(0b0001_1110, &[b"client", b"will/topic", b"bye"]);
+        assert_eq!(Connect::from_bytes(&buf), Err(Error::InvalidQoS));
+    }
+
+    #[test]
+    fn preview_flags() {
+        let inputs: [(u8, &[&[u8]]); 4] = [
+            (0b0000_0000, &[b"client"]),
+            (0b0000_0010, &[b"client"]),
+            (0b1100_0010, &[b"client", b"user", b"pass"]),
+            (0b1011_0100, &[b"client", b"will/topic", b"bye", b"user"]),
+        ];
+        for (flags, payload) in inputs.iter() {
+            let buf = connect_bytes(*flags, payload);
+            let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+            assert_eq!(connect.preview_flags(), *flags);
+        }
+
+        let buf = connect_bytes(0b0000_0011, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.preview_flags(), 0b0000_0010);
+        let connect = connect.with_injected_username("device-cn");
+        assert_eq!(connect.preview_flags(), 0b1000_0010);
+        assert_eq!(connect.preview_flags(), connect.computed_flags());
+    }
+
+    #[test]
+    fn default_connack() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connack = Connect::from_bytes(&buf)
+            .unwrap()
+            .unwrap()
+            .default_connack();
+        assert!(!connack.session_present());
+        assert_eq!(connack.return_code(), ConnackReturnCode::Accepted);
+
+        let connack = Connect::new("client")
+            .with_clean_session(false)
+            .default_connack();
+        assert!(connack.session_present());
+    }
+
+    #[test]
+    fn supported_version() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(connect.is_supported_version(&[4]));
+        assert!(connect.is_supported_version(&[3, 4]));
+        assert_eq!(connect.version_rejection(&[4]), None);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut buf = connect_bytes(0b0000_0010, &[b"client"]);
+        buf[6] = 5;
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(!connect.is_supported_version(&[4]));
+
+        let connack = connect.version_rejection(&[4]).unwrap();
+        assert!(!connack.session_present());
+        assert_eq!(
+            connack.return_code(),
+            ConnackReturnCode::UnacceptableProtocol
+        );
+    }
+
+    #[test]
+    fn minimal_connect() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.client_id(), "client");
+        assert_eq!(connect.will_topic(), None);
+        assert_eq!(connect.will_message(), None);
+        assert_eq!(connect.username(), None);
+        assert_eq!(connect.password(), None);
+    }
+
+    #[test]
+    fn partial() {
+        let buf = connect_bytes(0b1100_0010, &[b"client", b"user", b"pass"]);
+        for len in 0..buf.len() {
+            let result = Connect::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(result, Status::Partial);
+        }
+    }
+
+    #[test]
+    fn persistent_session() {
+        let buf = connect_bytes(0b0000_0000, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(!connect.clean_session());
+        assert!(connect.wants_persistent_session());
+    }
+
+    #[test]
+    fn clean_session() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(connect.clean_session());
+        assert!(!connect.wants_persistent_session());
+    }
+
+    #[test]
+    fn consumed() {
+        let mut buf = connect_bytes(0b1100_0010, &[b"client", b"user", b"pass"]);
+        let len = buf.len();
+        // trailing bytes belong to the next packet
+        buf.push(&[0xC0, 0x00]);
+        let (_, read) = Connect::from_bytes_consumed(&buf).unwrap().unwrap();
+        assert_eq!(read, len);
+    }
+
+    #[test]
+    fn first_packet_connect() {
+        let body = connect_bytes(0b0000_0010, &[b"client"]);
+        let mut buf = vec![1 << 4, body.len() as u8];
+        buf.extend_from_slice(&body);
+
+        let connect = expect_connect(&buf).unwrap().unwrap();
+        assert_eq!(connect.client_id(), "client");
+        assert_eq!(
+            expect_connect(&buf[..buf.len() - 1]).unwrap(),
+            Status::Partial
+        );
+    }
+
+    #[test]
+    fn first_packet_bad_length() {
+        // remaining length ending inside the protocol name
+        let buf = [0x10, 2, 0, 4, b'M', b'Q', b'T', b'T', 4, 2, 0, 60];
+        assert_eq!(expect_connect(&buf), Err(Error::InvalidLength));
+
+        // trailing bytes within the remaining length
+        let body = connect_bytes(0b0000_0010, &[b"client"]);
+        let mut buf = vec![1 << 4, body.len() as u8 + 1];
+        buf.extend_from_slice(&body);
+        buf.push(0);
+        assert_eq!(expect_connect(&buf), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn first_packet_publish() {
+        let buf = [3 << 4, 3, 0, 1, b'a'];
+        assert_eq!(expect_connect(&buf), Err(Error::FirstPacketNotConnect));
+    }
+
+    #[test]
+    fn injected_username() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert!(!connect.username_present());
+
+        let connect = connect.with_injected_username("device-cn");
+        assert!(connect.username_present());
+        assert!(!connect.password_present());
+        assert!(connect.clean_session());
+        assert_eq!(connect.username(), Some("device-cn"));
+        assert_eq!(connect.client_id(), "client");
+
+        let mut out = [0u8; 64];
+        let len = connect.to_bytes(&mut out).unwrap();
+        let decoded = expect_connect(&out[..len]).unwrap().unwrap();
+        assert_eq!(decoded, connect);
+        assert_eq!(*decoded.flags(), 0b1000_0010);
+        assert_eq!(decoded.username(), Some("device-cn"));
+    }
+
+    #[test]
+    fn to_bytes_round_trip() {
+        let buf = connect_bytes(
+            0b1111_0110,
+            &[b"client", b"will/topic", b"bye", b"user", b"secret"],
+        );
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+
+        let mut out = [0u8; 64];
+        let len = connect.to_bytes(&mut out).unwrap();
+        assert_eq!(len, 2 + buf.len());
+        assert_eq!(&out[2..len], &buf[..]);
+        assert_eq!(expect_connect(&out[..len]), Ok(Status::Complete(connect)));
+
+        assert_eq!(
+            connect.to_bytes(&mut out[..len - 1]),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn builder_flags() {
+        let connect = Connect::new("client").with_username("user");
+        assert_eq!(connect.computed_flags(), 0b1000_0010);
+        assert!(connect.username_present());
+
+        let connect = Connect::new("client")
+            .with_clean_session(false)
+            .with_will_topic("will/topic")
+            .with_will_qos(QoS::AtLeastOnce)
+            .with_will_retain(true);
+        assert!(connect.will_flag());
+        assert_eq!(connect.computed_flags(), 0b0010_1100);
+        assert_eq!(connect.flags(), &connect.computed_flags());
+
+        let connect = Connect::new("client").with_will_msg(b"bye");
+        assert_eq!(connect.computed_flags(), 0b0000_0110);
+    }
+
+    #[test]
+    fn inconsistent_builder() {
+        let mut buf = [0u8; 64];
+        let connect = Connect::new("client").with_password(b"secret");
+        assert_eq!(
+            connect.to_bytes(&mut buf),
+            Err(Error::PasswordWithoutUsername)
+        );
+
+        let connect = Connect::new("client").with_will_qos(QoS::AtLeastOnce);
+        assert_eq!(
+            connect.to_bytes(&mut buf),
+            Err(Error::WillFieldWithoutWillFlag)
+        );
+
+        let connect = Connect::new("client").with_will_retain(true);
+        assert_eq!(
+            connect.to_bytes(&mut buf),
+            Err(Error::WillFieldWithoutWillFlag)
+        );
+        assert_eq!(buf, [0u8; 64]);
+    }
+
+    #[test]
+    fn field_too_long() {
+        let long = [b'a'; u16::MAX as usize + 1];
+        let long = core::str::from_utf8(&long).unwrap();
+        let mut buf = [0u8; 64];
+
+        let connect = Connect::new(long);
+        assert_eq!(connect.to_bytes(&mut buf), Err(Error::InvalidLength));
+        let connect = Connect::new("client").with_will_topic(long);
+        assert_eq!(connect.to_bytes(&mut buf), Err(Error::InvalidLength));
+        let connect = Connect::new("client")
+            .with_username("user")
+            .with_password(long.as_bytes());
+        assert_eq!(connect.to_bytes(&mut buf), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let connect = Connect::new("client")
+            .with_keep_alive(30)
+            .with_will_topic("will/topic")
+            .with_will_msg(b"bye")
+            .with_username("user")
+            .with_password(b"secret");
+
+        let mut buf = [0u8; 64];
+        let len = connect.to_bytes(&mut buf).unwrap();
+        assert_eq!(expect_connect(&buf[..len]), Ok(Status::Complete(connect)));
+    }
+
+    #[test]
+    fn without_will() {
+        let buf = connect_bytes(0b1011_0110, &[b"client", b"will/topic", b"bye", b"user"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap().without_will();
+        assert!(!connect.will_flag());
+        assert!(!connect.will_retain());
+        assert_eq!(connect.will_qos(), QoS::AtMostOnce);
+        assert_eq!(connect.check_consistency(), Ok(()));
+
+        let mut out = [0u8; 64];
+        let len = connect.to_bytes(&mut out).unwrap();
+        let decoded = expect_connect(&out[..len]).unwrap().unwrap();
+        assert_eq!(decoded, connect);
+        assert_eq!(*decoded.flags(), 0b1000_0010);
+        assert_eq!(decoded.will_topic(), None);
+        assert_eq!(decoded.username(), Some("user"));
+    }
+
+    #[test]
+    fn validated_protocol_name() {
+        let buf = connect_bytes(0b0000_0010, &[b"client"]);
+        let connect = Connect::from_bytes_validated(&buf).unwrap().unwrap();
+        assert_eq!(connect.name(), "MQTT");
+
+        let mut buf = connect_bytes(0b0000_0010, &[b"client"]);
+        buf[2..6].copy_from_slice(b"HTTP");
+        assert_eq!(
+            Connect::from_bytes_validated(&buf),
+            Err(Error::InvalidProtocolName)
+        );
+        assert!(Connect::from_bytes(&buf).is_ok());
+    }
+
+    #[test]
+    fn validated_reserved_flag() {
+        let buf = connect_bytes(0b0000_0011, &[b"client"]);
+        assert_eq!(
+            Connect::from_bytes_validated(&buf),
+            Err(Error::InvalidConnectFlag)
+        );
+        assert!(Connect::from_bytes(&buf).is_ok());
+    }
+
+    #[test]
+    fn username_split() {
+        let buf = connect_bytes(0b1000_0010, &[b"client", b"acme:bob"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.username_split(':'), Some(("acme", "bob")));
+
+        let buf = connect_bytes(0b1000_0010, &[b"client", b"bob"]);
+        let connect = Connect::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(connect.username_split(':'), None);
+        assert_eq!(Connect::new("client").username_split(':'), None);
+    }
+
+    #[test]
+    fn validated_empty_client_id() {
+        let buf = connect_bytes(0b0000_0010, &[b""]);
+        let connect = Connect::from_bytes_validated(&buf).unwrap().unwrap();
+        assert_eq!(connect.client_id(), "");
+
+        let buf = connect_bytes(0b0000_0000, &[b""]);
+        assert_eq!(
+            Connect::from_bytes_validated(&buf),
+            Err(Error::EmptyClientId)
+        );
+        assert!(Connect::from_bytes(&buf).is_ok());
+    }
 }
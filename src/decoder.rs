@@ -0,0 +1,238 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use super::parse;
+use super::{parse_bounded, Error, Packet, Result, Status};
+
+/// Accumulates bytes read from a stream and decodes the packets within.
+///
+/// The packets are decoded in place from the backing buffer `B` (e.g. a
+/// `[u8; N]` array or a `&mut [u8]`), which must be large enough to hold the
+/// largest expected packet. The bytes of a yielded packet are released on the
+/// following call to `push` or `next`.
+#[derive(Debug)]
+pub struct Decoder<B> {
+    buf: B,
+    len: usize,
+    consumed: usize,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Decoder<B> {
+    pub fn new(buf: B) -> Decoder<B> {
+        Decoder {
+            buf,
+            len: 0,
+            consumed: 0,
+        }
+    }
+
+    /// Appends `data` to the buffered bytes.
+    ///
+    /// Fails with `Error::PacketTooLarge` when `data` does not fit into the
+    /// backing buffer along with the bytes already buffered, in which case
+    /// nothing is appended.
+    pub fn push(&mut self, data: &[u8]) -> Result<()> {
+        self.compact();
+
+        let buf = self.buf.as_mut();
+        if buf.len() - self.len < data.len() {
+            return Err(Error::PacketTooLarge);
+        }
+
+        buf[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        Ok(())
+    }
+
+    /// Decodes the next buffered packet, or returns `Ok(None)` when more bytes
+    /// are needed to complete it.
+    ///
+    /// Fails with `Error::PacketTooLarge` when the buffered fixed header
+    /// announces a packet larger than the backing buffer.
+    // the packet borrows from the decoder, so this cannot be an `Iterator`
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Packet<'_>>> {
+        self.compact();
+
+        // a packet that cannot fit into the backing buffer is rejected as
+        // soon as its fixed header is in, rather than never completing
+        let capacity = self.buf.as_ref().len();
+        let max_remaining_length = capacity.saturating_sub(2).min(268_435_455) as u32;
+        let buf = &self.buf.as_ref()[..self.len];
+        match parse_bounded(buf, max_remaining_length)? {
+            Status::Complete((packet, read)) => {
+                self.consumed = read;
+                Ok(Some(packet))
+            }
+            Status::Partial if self.len == capacity => Err(Error::PacketTooLarge),
+            Status::Partial => Ok(None),
+        }
+    }
+
+    /// Returns the number of buffered bytes not yet returned as a packet.
+    pub fn buffered(&self) -> usize {
+        self.len - self.consumed
+    }
+
+    fn compact(&mut self) {
+        if self.consumed > 0 {
+            self.buf.as_mut().copy_within(self.consumed..self.len, 0);
+            self.len -= self.consumed;
+            self.consumed = 0;
+        }
+    }
+}
+
+/// Same as `Decoder` but backed by a `Vec<u8>` which grows to hold whatever
+/// is appended, so no packet is too large for it.
+///
+/// The bytes of a yielded packet are released on the following call to
+/// `extend` or `next`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Default)]
+pub struct PacketBuffer {
+    buf: Vec<u8>,
+    consumed: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl PacketBuffer {
+    pub fn new() -> PacketBuffer {
+        PacketBuffer::default()
+    }
+
+    /// Appends `data` to the buffered bytes.
+    pub fn extend(&mut self, data: &[u8]) {
+        self.compact();
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Decodes the next buffered packet, or returns `Ok(None)` when more bytes
+    /// are needed to complete it.
+    // the packet borrows from the buffer, so this cannot be an `Iterator`
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Packet<'_>>> {
+        self.compact();
+
+        match parse(&self.buf)? {
+            Status::Complete((packet, read)) => {
+                self.consumed = read;
+                Ok(Some(packet))
+            }
+            Status::Partial => Ok(None),
+        }
+    }
+
+    /// Returns the number of buffered bytes not yet returned as a packet.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.consumed
+    }
+
+    fn compact(&mut self) {
+        self.buf.drain(..self.consumed);
+        self.consumed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::PUBLISH;
+    use {PacketType, Pingreq};
+
+    #[test]
+    fn byte_by_byte() {
+        let mut decoder = Decoder::new([0u8; 16]);
+        for (i, byte) in PUBLISH.iter().enumerate() {
+            decoder.push(&[*byte]).unwrap();
+            let packet = decoder.next().unwrap();
+            if i < PUBLISH.len() - 1 {
+                assert_eq!(packet, None);
+            } else {
+                assert_eq!(packet.unwrap().packet_type(), PacketType::Publish);
+            }
+        }
+        assert_eq!(decoder.next(), Ok(None));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn multiple_packets() {
+        let mut buf = [0u8; 16];
+        let mut decoder = Decoder::new(&mut buf[..]);
+        decoder.push(&PUBLISH).unwrap();
+        decoder.push(&[12 << 4, 0, 3 << 4]).unwrap();
+
+        match decoder.next().unwrap() {
+            Some(Packet::Publish(publish)) => assert_eq!(publish.payload(), b"hi"),
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+        assert_eq!(decoder.next(), Ok(Some(Packet::Pingreq(Pingreq))));
+        assert_eq!(decoder.next(), Ok(None));
+        assert_eq!(decoder.buffered(), 1);
+    }
+
+    #[test]
+    fn packet_too_large() {
+        let mut decoder = Decoder::new([0u8; 16]);
+        // PUBLISH declaring the maximum remaining length of 268 MB
+        decoder.push(&[3 << 4, 0xFF, 0xFF, 0xFF, 0x7F]).unwrap();
+        assert_eq!(decoder.next(), Err(Error::PacketTooLarge));
+
+        // a remaining length of 128 takes two bytes, one too many to fit
+        let mut decoder = Decoder::new([0u8; 130]);
+        decoder.push(&[3 << 4, 0x80, 0x01, 0, 1, b'a']).unwrap();
+        assert_eq!(decoder.next(), Ok(None));
+        decoder.push(&[0; 124]).unwrap();
+        assert_eq!(decoder.next(), Err(Error::PacketTooLarge));
+    }
+
+    #[test]
+    fn buffer_full() {
+        let mut decoder = Decoder::new([0u8; 8]);
+        assert_eq!(decoder.push(&PUBLISH), Err(Error::PacketTooLarge));
+        assert_eq!(decoder.buffered(), 0);
+
+        decoder.push(&[12 << 4, 0]).unwrap();
+        assert!(decoder.next().unwrap().is_some());
+        // the yielded packet is released on the next push
+        decoder.push(&[0; 8]).unwrap();
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn packet_buffer() {
+        let mut bytes = PUBLISH.to_vec();
+        bytes.extend_from_slice(&[12 << 4, 0]);
+        bytes.extend_from_slice(&PUBLISH);
+
+        let mut buffer = PacketBuffer::new();
+        let mut packets = Vec::new();
+        for chunk in bytes.chunks(4) {
+            buffer.extend(chunk);
+            while let Some(packet) = buffer.next().unwrap() {
+                packets.push(packet.packet_type());
+            }
+        }
+        assert_eq!(
+            packets,
+            [
+                PacketType::Publish,
+                PacketType::Pingreq,
+                PacketType::Publish
+            ]
+        );
+        assert_eq!(buffer.buffered(), 0);
+
+        // grows beyond any initial capacity for a large packet
+        let mut buffer = PacketBuffer::new();
+        buffer.extend(&[3 << 4, 0x83, 0x01, 0, 1, b'a']);
+        assert_eq!(buffer.next(), Ok(None));
+        buffer.extend(&[0; 128]);
+        match buffer.next().unwrap() {
+            Some(Packet::Publish(publish)) => assert_eq!(publish.payload_len(), 128),
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+}
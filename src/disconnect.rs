@@ -0,0 +1,50 @@
+use super::{Encode, EncodeBody, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Disconnect;
+
+impl Disconnect {
+    /// A DISCONNECT is just the fixed header in MQTT 3.1.1.
+    pub fn from_bytes(_bytes: &[u8]) -> Result<Status<Disconnect>> {
+        Ok(Status::Complete(Disconnect))
+    }
+
+    /// Writes the two byte DISCONNECT packet into `buf`, returning the number
+    /// of bytes written.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize> {
+        self.encode(buf)
+    }
+}
+
+impl EncodeBody for Disconnect {
+    fn fixed_header_byte(&self) -> u8 {
+        14 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        0
+    }
+
+    fn encode_body(&self, _buf: &mut [u8]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Error;
+
+    #[test]
+    fn disconnect() {
+        let mut buf = [0xFFu8; 2];
+        assert_eq!(Disconnect.to_bytes(&mut buf), Ok(2));
+        assert_eq!(buf, [0xE0, 0x00]);
+        assert_eq!(
+            Disconnect::from_bytes(&[]).unwrap(),
+            Status::Complete(Disconnect)
+        );
+        assert_eq!(
+            Disconnect.to_bytes(&mut buf[..1]),
+            Err(Error::InvalidLength)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use super::header::encode_remaining_length;
+use super::{Error, Result};
+
+/// Encoding of a control packet into a caller supplied buffer.
+///
+/// `encode` writes the fixed header followed by the variable header and
+/// payload, and `encoded_len` reports the total size so a buffer can be sized
+/// exactly beforehand. The trait is sealed: it is implemented for every
+/// packet type of this crate and cannot be implemented outside of it.
+pub trait Encode: EncodeBody {
+    /// Returns the total number of bytes written by `encode`.
+    fn encoded_len(&self) -> usize {
+        packet_len(self.body_len())
+    }
+
+    /// Encodes the packet, including the fixed header, into `buf` and
+    /// returns the number of bytes written.
+    ///
+    /// A body exceeding the maximum remaining length results in
+    /// `Error::PacketTooLarge` and a buffer too small for the packet in
+    /// `Error::InvalidLength`. Nothing is written to `buf` on error.
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        self.validate()?;
+
+        let body_len = self.body_len();
+        if body_len > 268_435_455 {
+            return Err(Error::PacketTooLarge);
+        }
+
+        let mut header = [0u8; 4];
+        let header_len = encode_remaining_length(body_len as u32, &mut header);
+        let len = 1 + header_len + body_len;
+        if buf.len() < len {
+            return Err(Error::InvalidLength);
+        }
+
+        buf[0] = self.fixed_header_byte();
+        buf[1..1 + header_len].copy_from_slice(&header[..header_len]);
+        self.encode_body(&mut buf[1 + header_len..len]);
+        Ok(len)
+    }
+}
+
+impl<T: EncodeBody> Encode for T {}
+
+pub(crate) use self::body::EncodeBody;
+
+mod body {
+    use Result;
+
+    /// The per packet part of `Encode`, kept out of reach of other crates
+    /// so that `encode_body` is only ever called with a buffer of the right
+    /// size.
+    pub trait EncodeBody {
+        /// Returns the first byte of the fixed header, i.e. the packet type
+        /// and flags.
+        fn fixed_header_byte(&self) -> u8;
+
+        /// Returns the size of the variable header and payload, which is the
+        /// remaining length announced in the fixed header.
+        fn body_len(&self) -> usize;
+
+        /// Writes the variable header and payload into `buf`, which is
+        /// exactly `body_len` bytes long.
+        fn encode_body(&self, buf: &mut [u8]);
+
+        /// Checks that the packet can be encoded before anything is written.
+        fn validate(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
+
+/// Total size of a packet with the given remaining length, including the
+/// fixed header.
+pub(crate) fn packet_len(remaining_length: usize) -> usize {
+    let header_len = match remaining_length {
+        0..=127 => 2,
+        128..=16_383 => 3,
+        16_384..=2_097_151 => 4,
+        _ => 5,
+    };
+    header_len + remaining_length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {encode_publish, parse, Connect, Packet, QoS, Status};
+
+    #[test]
+    fn packet_lengths() {
+        assert_eq!(packet_len(0), 2);
+        assert_eq!(packet_len(127), 129);
+        assert_eq!(packet_len(128), 131);
+        assert_eq!(packet_len(16_384), 16_388);
+    }
+
+    #[test]
+    fn connect_len() {
+        let connect = Connect::new("client")
+            .with_will_topic("will/topic")
+            .with_will_msg(b"bye")
+            .with_username("user");
+
+        let mut buf = [0u8; 64];
+        let len = connect.encode(&mut buf).unwrap();
+        assert_eq!(len, connect.encoded_len());
+        assert_eq!(
+            parse(&buf[..len]),
+            Ok(Status::Complete((Packet::Connect(connect), len)))
+        );
+        assert_eq!(
+            connect.encode(&mut buf[..len - 1]),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn publish_len() {
+        let mut buf = [0u8; 200];
+        let payload = [0x55; 150];
+        let len = encode_publish(
+            "a/b",
+            &payload,
+            QoS::AtLeastOnce,
+            Some(1),
+            false,
+            false,
+            &mut buf,
+        )
+        .unwrap();
+
+        let publish = match parse(&buf[..len]).unwrap().unwrap() {
+            (Packet::Publish(publish), _) => publish,
+            packet => panic!("unexpected packet {:?}", packet),
+        };
+        // a remaining length above 127 takes two bytes
+        assert_eq!(publish.encoded_len(), 1 + 2 + 2 + 3 + 2 + 150);
+
+        let mut out = [0u8; 200];
+        assert_eq!(publish.encode(&mut out), Ok(publish.encoded_len()));
+        assert_eq!(&out[..len], &buf[..len]);
+    }
+}
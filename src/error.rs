@@ -16,6 +16,44 @@ pub enum Error {
     Utf8,
     /// Invalid connect flag value
     InvalidConnectFlag,
+    /// Packet exceeds the maximum packet size
+    PacketTooLarge,
+    /// First packet on a connection is not a CONNECT
+    FirstPacketNotConnect,
+    /// Packet is not allowed in the current connection state
+    ProtocolViolation,
+    /// Password flag set without the user name flag
+    PasswordWithoutUsername,
+    /// Will QoS, retain or will fields present without the will flag
+    WillFieldWithoutWillFlag,
+    /// Acknowledgement for a packet identifier that was never requested
+    UnexpectedAck,
+    /// Invalid QoS value
+    InvalidQoS,
+    /// QoS above the maximum supported by the server
+    QoSExceedsMaximum,
+    /// Reserved connack flag bits set
+    InvalidConnackFlag,
+    /// Invalid connack return code
+    InvalidConnackReturnCode,
+    /// Invalid combination of publish flags
+    InvalidPublishFlags,
+    /// Invalid suback return code
+    InvalidSubackReturnCode,
+    /// Invalid topic name
+    InvalidTopicName,
+    /// Missing, unexpected or zero packet identifier
+    InvalidPacketId,
+    /// Protocol name other than "MQTT" for a MQTT 3.1.1 CONNECT
+    InvalidProtocolName,
+    /// Zero length client identifier without clean session
+    EmptyClientId,
+    /// Both QoS bits set in the fixed header of a PUBLISH
+    InvalidPublishQoS,
+    /// Invalid topic filter
+    InvalidTopicFilter,
+    /// Packet or protocol version not supported for the requested operation
+    UnsupportedForVersion,
 }
 
 impl Error {
@@ -27,6 +65,25 @@ impl Error {
             Error::InvalidLength => "invalid buffer length",
             Error::Utf8 => "invalid utf-8 encoding",
             Error::InvalidConnectFlag => "invalid connect flag value",
+            Error::PacketTooLarge => "packet exceeds the maximum packet size",
+            Error::FirstPacketNotConnect => "first packet is not a connect packet",
+            Error::ProtocolViolation => "packet not allowed in current connection state",
+            Error::PasswordWithoutUsername => "password present without user name",
+            Error::WillFieldWithoutWillFlag => "will field present without will flag",
+            Error::UnexpectedAck => "acknowledgement for unknown packet identifier",
+            Error::InvalidQoS => "invalid qos value",
+            Error::QoSExceedsMaximum => "qos exceeds the maximum qos",
+            Error::InvalidConnackFlag => "invalid connack flag value",
+            Error::InvalidConnackReturnCode => "invalid connack return code",
+            Error::InvalidPublishFlags => "invalid publish flags",
+            Error::InvalidSubackReturnCode => "invalid suback return code",
+            Error::InvalidTopicName => "invalid topic name",
+            Error::InvalidPacketId => "invalid packet identifier",
+            Error::InvalidProtocolName => "invalid protocol name",
+            Error::EmptyClientId => "empty client identifier without clean session",
+            Error::InvalidPublishQoS => "invalid publish qos",
+            Error::InvalidTopicFilter => "invalid topic filter",
+            Error::UnsupportedForVersion => "unsupported for the protocol version",
         }
     }
 }
@@ -1,83 +1,205 @@
-use super::{Error, PacketType, PacketTypeFlags, Result, Status};
+use byteorder::{BigEndian, ByteOrder};
 
+use super::{parse_bytes, read_varint, Error, PacketType, PacketTypeFlags, Result, Status};
+
+/// The fixed header present at the start of every MQTT control packet.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub struct Header {
-    type_: PacketType,
+pub struct FixedHeader {
+    packet_type: PacketType,
     flags: PacketTypeFlags,
-    len: u32,
+    remaining_length: u32,
+    remaining_length_bytes: [u8; 4],
 }
 
-impl Header {
-    pub fn from_bytes(bytes: &[u8]) -> Result<Status<Header>> {
+impl FixedHeader {
+    /// Decodes the fixed header at the start of `bytes`, returning it together
+    /// with the number of bytes it occupies.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Status<(FixedHeader, usize)>> {
         // "bytes" must be at least 2 bytes long to be a valid fixed header
         if bytes.len() < 2 {
             return Ok(Status::Partial);
         }
 
-        let (type_, flags) = parse_packet_type(bytes[0])?;
-        let (len, _) = parse_remaining_length(&bytes[1..])?;
+        let (packet_type, flags) = parse_packet_type(bytes[0])?;
+        let (remaining_length, read) = complete!(read_varint(&bytes[1..]));
+        let mut remaining_length_bytes = [0; 4];
+        remaining_length_bytes[..read].copy_from_slice(&bytes[1..1 + read]);
+
+        Ok(Status::Complete((
+            FixedHeader {
+                packet_type,
+                flags,
+                remaining_length,
+                remaining_length_bytes,
+            },
+            1 + read,
+        )))
+    }
 
-        Ok(Status::Complete(Header { type_, flags, len }))
+    /// Same as `from_bytes` but fails with `Error::PacketTooLarge` as soon as
+    /// the declared remaining length exceeds `max_remaining_length`, before
+    /// the packet body has been buffered.
+    pub fn from_bytes_bounded(
+        bytes: &[u8],
+        max_remaining_length: u32,
+    ) -> Result<Status<(FixedHeader, usize)>> {
+        let (header, read) = complete!(FixedHeader::from_bytes(bytes));
+        if header.remaining_length > max_remaining_length {
+            return Err(Error::PacketTooLarge);
+        }
+
+        Ok(Status::Complete((header, read)))
     }
 
-    pub fn type_(&self) -> &PacketType {
-        &self.type_
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
     }
 
-    pub fn flags(&self) -> &PacketTypeFlags {
-        &self.flags
+    pub fn flags(&self) -> PacketTypeFlags {
+        self.flags
     }
 
+    pub fn remaining_length(&self) -> u32 {
+        self.remaining_length
+    }
+
+    /// Returns the remaining length exactly as it was encoded, which may use
+    /// more bytes than necessary since the parser accepts non-minimal
+    /// encodings.
+    pub fn remaining_length_bytes(&self) -> &[u8] {
+        // every byte but the last has the continuation bit set
+        let len = self
+            .remaining_length_bytes
+            .iter()
+            .position(|byte| byte & 0x80 == 0)
+            .map_or(4, |i| i + 1);
+        &self.remaining_length_bytes[..len]
+    }
+
+    /// Same as `from_bytes`, kept from the `Header` API.
+    pub fn from_bytes_consumed(bytes: &[u8]) -> Result<Status<(FixedHeader, usize)>> {
+        FixedHeader::from_bytes(bytes)
+    }
+
+    /// Same as `packet_type`, kept from the `Header` API.
+    pub fn type_(&self) -> &PacketType {
+        &self.packet_type
+    }
+
+    /// Same as `remaining_length`, kept from the `Header` API.
     pub fn len(&self) -> &u32 {
-        &self.len
+        &self.remaining_length
     }
 
+    /// Returns `true` when the remaining length is zero.
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.remaining_length == 0
     }
 }
 
-fn parse_remaining_length(bytes: &[u8]) -> Result<(u32, usize)> {
-    let mut multiplier = 1;
-    let mut value = 0u32;
-    let mut index = 0;
+/// The former name of `FixedHeader`.
+pub type Header = FixedHeader;
 
-    loop {
-        if multiplier > 128 * 128 * 128 {
-            break Err(Error::RemainingLength);
-        } else if index >= bytes.len() {
-            break Err(Error::InvalidLength);
+/// Decodes the remaining length field at the start of `bytes`, returning the
+/// value and the number of bytes it occupied.
+///
+/// This is the same decoder as `read_varint`, which should be used instead.
+#[deprecated(note = "use `read_varint`")]
+pub fn decode_remaining_length(bytes: &[u8]) -> Result<Status<(u32, usize)>> {
+    read_varint(bytes)
+}
+
+/// Reads just the packet type and remaining length from the start of a
+/// packet without decoding anything past the fixed header.
+pub fn quick_stats(bytes: &[u8]) -> Result<Status<(PacketType, u32)>> {
+    let (header, _) = complete!(FixedHeader::from_bytes(bytes));
+    Ok(Status::Complete((
+        header.packet_type,
+        header.remaining_length,
+    )))
+}
+
+/// Overwrites the packet identifier of the packet at the start of `buf` with
+/// `new_id`, leaving every other byte as is.
+///
+/// Returns `Error::PacketType` for packets which don't carry a packet
+/// identifier (including QoS 0 PUBLISH packets), `Error::InvalidPublishQoS`
+/// for a PUBLISH with both QoS bits set and `Error::InvalidLength` when the
+/// identifier does not lie within the remaining length of the packet or `buf`
+/// is too short to hold it.
+pub fn rewrite_packet_id_in_place(buf: &mut [u8], new_id: u16) -> Result<()> {
+    let (header, mut read) = match FixedHeader::from_bytes(buf)? {
+        Status::Complete(v) => v,
+        Status::Partial => return Err(Error::InvalidLength),
+    };
+
+    // the packet identifier must lie within the remaining length
+    let end = read + header.remaining_length as usize;
+    if buf.len() < end {
+        return Err(Error::InvalidLength);
+    }
+
+    match header.packet_type {
+        PacketType::Publish => {
+            // QoS 0 PUBLISH packets have no packet identifier, and a PUBLISH
+            // MUST NOT have both QoS bits set (MQTT-3.3.1-4)
+            match header.flags & 0b0110 {
+                0b0000 => return Err(Error::PacketType),
+                0b0110 => return Err(Error::InvalidPublishQoS),
+                _ => {}
+            }
+            // packet identifier follows the topic name
+            match parse_bytes(&buf[read..end])? {
+                Status::Complete(topic) => read += 2 + topic.len(),
+                Status::Partial => return Err(Error::InvalidLength),
+            }
         }
+        PacketType::Puback
+        | PacketType::Pubrec
+        | PacketType::Pubrel
+        | PacketType::Pubcomp
+        | PacketType::Subscribe
+        | PacketType::Suback
+        | PacketType::Unsubscribe
+        | PacketType::Unsuback => {}
+        _ => return Err(Error::PacketType),
+    }
+
+    if end - read < 2 {
+        return Err(Error::InvalidLength);
+    }
+    BigEndian::write_u16(&mut buf[read..], new_id);
+    Ok(())
+}
 
-        let byte = bytes[index];
+pub(crate) fn encode_remaining_length(mut len: u32, buf: &mut [u8; 4]) -> usize {
+    let mut index = 0;
+    loop {
+        let mut byte = len as u8 % 128;
+        len /= 128;
+        if len > 0 {
+            byte |= 128;
+        }
+        buf[index] = byte;
         index += 1;
-        value += (byte & 127) as u32 * multiplier;
-        multiplier *= 128;
-        if byte & 128 == 0 {
-            break Ok((value, index));
+
+        if len == 0 {
+            break index;
         }
     }
 }
 
+/// Returns the packet type encoded in the high nibble of the first byte of a
+/// packet, without looking at the flags or the remaining length.
+///
+/// The reserved values 0 and 15 result in `Error::PacketType`.
+pub fn packet_type_of(first_byte: u8) -> Result<PacketType> {
+    PacketType::from_u8(first_byte >> 4)
+}
+
 fn parse_packet_type(inp: u8) -> Result<(PacketType, PacketTypeFlags)> {
     // high 4 bits are the packet type
-    let packet_type = match (inp & 0xF0) >> 4 {
-        1 => Ok(PacketType::Connect),
-        2 => Ok(PacketType::Connack),
-        3 => Ok(PacketType::Publish),
-        4 => Ok(PacketType::Puback),
-        5 => Ok(PacketType::Pubrec),
-        6 => Ok(PacketType::Pubrel),
-        7 => Ok(PacketType::Pubcomp),
-        8 => Ok(PacketType::Subscribe),
-        9 => Ok(PacketType::Suback),
-        10 => Ok(PacketType::Unsubscribe),
-        11 => Ok(PacketType::Unsuback),
-        12 => Ok(PacketType::Pingreq),
-        13 => Ok(PacketType::Pingresp),
-        14 => Ok(PacketType::Disconnect),
-        _ => Err(Error::PacketType),
-    }?;
+    let packet_type = packet_type_of(inp)?;
 
     // low 4 bits represent control flags
     let flags = inp & 0xF;
@@ -119,7 +241,7 @@ fn validate_flag_val(
     types: &[PacketType],
     expected_flags: PacketTypeFlags,
 ) -> Result<(PacketType, PacketTypeFlags)> {
-    if types.iter().any(|&v| v == packet_type) && flags != expected_flags {
+    if types.contains(&packet_type) && flags != expected_flags {
         return Err(Error::PacketFlag);
     }
 
@@ -127,22 +249,24 @@ fn validate_flag_val(
 }
 
 #[cfg(test)]
+#[allow(clippy::identity_op)]
 mod tests {
     use super::*;
     use rayon::prelude::*;
+    use {parse, Packet, QoS};
 
     #[test]
     fn packet_type() {
         let mut inputs: [([u8; 1], PacketType); 14] = [
-            ([01 << 4 | 0b0000], PacketType::Connect),
-            ([02 << 4 | 0b0000], PacketType::Connack),
-            ([03 << 4 | 0b0000], PacketType::Publish),
-            ([04 << 4 | 0b0000], PacketType::Puback),
-            ([05 << 4 | 0b0000], PacketType::Pubrec),
-            ([06 << 4 | 0b0010], PacketType::Pubrel),
-            ([07 << 4 | 0b0000], PacketType::Pubcomp),
-            ([08 << 4 | 0b0010], PacketType::Subscribe),
-            ([09 << 4 | 0b0000], PacketType::Suback),
+            ([1 << 4 | 0b0000], PacketType::Connect),
+            ([2 << 4 | 0b0000], PacketType::Connack),
+            ([3 << 4 | 0b0000], PacketType::Publish),
+            ([4 << 4 | 0b0000], PacketType::Puback),
+            ([5 << 4 | 0b0000], PacketType::Pubrec),
+            ([6 << 4 | 0b0010], PacketType::Pubrel),
+            ([7 << 4 | 0b0000], PacketType::Pubcomp),
+            ([8 << 4 | 0b0010], PacketType::Subscribe),
+            ([9 << 4 | 0b0000], PacketType::Suback),
             ([10 << 4 | 0b0010], PacketType::Unsubscribe),
             ([11 << 4 | 0b0000], PacketType::Unsuback),
             ([12 << 4 | 0b0000], PacketType::Pingreq),
@@ -164,15 +288,32 @@ mod tests {
         assert_eq!(result, Err(Error::PacketType));
     }
 
+    #[test]
+    fn first_byte_type() {
+        let inputs = [
+            (0x10, PacketType::Connect),
+            (0x3B, PacketType::Publish),
+            (0x82, PacketType::Subscribe),
+            (0xC0, PacketType::Pingreq),
+            // flags are not validated
+            (0xE5, PacketType::Disconnect),
+        ];
+        for &(first_byte, expected_type) in inputs.iter() {
+            assert_eq!(packet_type_of(first_byte), Ok(expected_type));
+        }
+        assert_eq!(packet_type_of(0x00), Err(Error::PacketType));
+        assert_eq!(packet_type_of(0xF0), Err(Error::PacketType));
+    }
+
     #[test]
     fn bad_zero_flags() {
         let mut inputs: [([u8; 1], PacketType); 10] = [
-            ([01 << 4 | 1], PacketType::Connect),
-            ([02 << 4 | 1], PacketType::Connack),
-            ([04 << 4 | 1], PacketType::Puback),
-            ([05 << 4 | 1], PacketType::Pubrec),
-            ([07 << 4 | 1], PacketType::Pubcomp),
-            ([09 << 4 | 1], PacketType::Suback),
+            ([1 << 4 | 1], PacketType::Connect),
+            ([2 << 4 | 1], PacketType::Connack),
+            ([4 << 4 | 1], PacketType::Puback),
+            ([5 << 4 | 1], PacketType::Pubrec),
+            ([7 << 4 | 1], PacketType::Pubcomp),
+            ([9 << 4 | 1], PacketType::Suback),
             ([11 << 4 | 1], PacketType::Unsuback),
             ([12 << 4 | 1], PacketType::Pingreq),
             ([13 << 4 | 1], PacketType::Pingresp),
@@ -187,8 +328,8 @@ mod tests {
     #[test]
     fn bad_one_flags() {
         let mut inputs: [([u8; 1], PacketType); 3] = [
-            ([06 << 4 | 0], PacketType::Pubrel),
-            ([08 << 4 | 0], PacketType::Subscribe),
+            ([6 << 4 | 0], PacketType::Pubrel),
+            ([8 << 4 | 0], PacketType::Subscribe),
             ([10 << 4 | 0], PacketType::Unsubscribe),
         ];
         for (buf, _) in inputs.iter_mut() {
@@ -200,30 +341,13 @@ mod tests {
     #[test]
     fn publish_flags() {
         for i in 0..15 {
-            let input = 03 << 4 | i;
+            let input = 3 << 4 | i;
             let (packet_type, flag) = parse_packet_type(input).unwrap();
             assert_eq!(packet_type, PacketType::Publish);
             assert_eq!(flag, i);
         }
     }
 
-    fn encode_remaining_length(mut len: u32, buf: &mut [u8; 4]) -> usize {
-        let mut index = 0;
-        loop {
-            let mut byte = len as u8 % 128;
-            len /= 128;
-            if len > 0 {
-                byte |= 128;
-            }
-            buf[index] = byte;
-            index = index + 1;
-
-            if len == 0 {
-                break index;
-            }
-        }
-    }
-
     #[test]
     fn remaining_length() {
         // NOTE: This test can take a while to complete.
@@ -232,8 +356,9 @@ mod tests {
             .map(|i| {
                 let mut buf = [0u8; 4];
                 let expected_index = encode_remaining_length(i, &mut buf);
-                let (len, index) =
-                    parse_remaining_length(&buf).expect(&format!("Failed for number: {}", i));
+                let (len, index) = read_varint(&buf)
+                    .unwrap_or_else(|_| panic!("Failed for number: {}", i))
+                    .unwrap();
                 assert_eq!(i, len);
                 assert_eq!(expected_index, index);
                 0
@@ -244,47 +369,192 @@ mod tests {
     #[test]
     fn bad_remaining_length() {
         let buf = [0xFF, 0xFF, 0xFF, 0xFF];
-        let result = parse_remaining_length(&buf);
+        let result = read_varint(&buf);
         assert_eq!(result, Err(Error::RemainingLength));
     }
 
     #[test]
     fn bad_remaining_length2() {
         let buf = [0xFF, 0xFF];
-        let result = parse_remaining_length(&buf);
-        assert_eq!(result, Err(Error::InvalidLength));
+        let result = read_varint(&buf);
+        assert_eq!(result, Ok(Status::Partial));
+
+        let result = FixedHeader::from_bytes(&[3 << 4 | 0, 0x80]);
+        assert_eq!(result, Ok(Status::Partial));
     }
 
     #[test]
     fn fixed_header1() {
         let buf = [
-            01 << 4 | 0b0000, // PacketType::Connect
-            0,                // remaining length
+            1 << 4 | 0b0000, // PacketType::Connect
+            0,               // remaining length
         ];
-        let header = Header::from_bytes(&buf).unwrap().unwrap();
-        assert_eq!(*header.type_(), PacketType::Connect);
-        assert_eq!(*header.flags(), 0);
-        assert_eq!(*header.len(), 0);
+        let (header, _) = FixedHeader::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(header.packet_type(), PacketType::Connect);
+        assert_eq!(header.flags(), 0);
+        assert_eq!(header.remaining_length(), 0);
     }
 
     #[test]
     fn fixed_header2() {
         let buf = [
-            03 << 4 | 0b0000, // PacketType::Publish
-            0x80,             // remaining length
+            3 << 4 | 0b0000, // PacketType::Publish
+            0x80,            // remaining length
             0x80,
             0x80,
             0x1,
         ];
-        let header = Header::from_bytes(&buf).unwrap().unwrap();
+        let (header, _) = FixedHeader::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(header.packet_type(), PacketType::Publish);
+        assert_eq!(header.flags(), 0);
+        assert_eq!(header.remaining_length(), 2097152);
+    }
+
+    #[test]
+    fn consumed() {
+        let buf = [
+            3 << 4 | 0b0000, // PacketType::Publish
+            0x80,            // remaining length
+            0x01,
+        ];
+        let (header, read) = FixedHeader::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(header.remaining_length(), 128);
+        assert_eq!(read, buf.len());
+    }
+
+    #[test]
+    fn header_api() {
+        let buf = [
+            3 << 4 | 0b0000, // PacketType::Publish
+            0x80,            // remaining length
+            0x01,
+        ];
+        let (header, read) = Header::from_bytes_consumed(&buf).unwrap().unwrap();
         assert_eq!(*header.type_(), PacketType::Publish);
-        assert_eq!(*header.flags(), 0);
-        assert_eq!(*header.len(), 2097152);
+        assert_eq!(*header.len(), 128);
+        assert!(!header.is_empty());
+        assert_eq!(read, buf.len());
+
+        let (header, _) = Header::from_bytes(&[12 << 4, 0]).unwrap().unwrap();
+        assert!(header.is_empty());
+    }
+
+    #[test]
+    fn raw_remaining_length() {
+        let inputs: [&[u8]; 3] = [
+            &[3 << 4, 0x00],
+            &[3 << 4, 0x80, 0x01],
+            // non-minimal encoding of 0
+            &[3 << 4, 0x80, 0x80, 0x00],
+        ];
+        for buf in inputs.iter() {
+            let (header, read) = FixedHeader::from_bytes(buf).unwrap().unwrap();
+            assert_eq!(read, buf.len());
+            assert_eq!(header.remaining_length_bytes(), &buf[1..]);
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let buf = [3 << 4 | 0b0000, 0x80, 0x01];
+        let (header, read) = FixedHeader::from_bytes_bounded(&buf, 128).unwrap().unwrap();
+        assert_eq!(header.remaining_length(), 128);
+        assert_eq!(read, 3);
+
+        let result = FixedHeader::from_bytes_bounded(&buf, 127);
+        assert_eq!(result, Err(Error::PacketTooLarge));
+    }
+
+    #[test]
+    fn rewrite_publish_id() {
+        let mut buf = [
+            3 << 4 | 0b0010, // PacketType::Publish, QoS 1
+            7,               // remaining length
+            0,
+            1,
+            b'a', // topic
+            0x12,
+            0x34, // packet id
+            b'h',
+            b'i', // payload
+        ];
+        rewrite_packet_id_in_place(&mut buf, 0xBEEF).unwrap();
+        match parse(&buf).unwrap().unwrap() {
+            (Packet::Publish(publish), 9) => {
+                assert_eq!(publish.packet_id(), Some(0xBEEF));
+                assert_eq!(publish.topic(), "a");
+                assert_eq!(publish.payload(), b"hi");
+            }
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn rewrite_subscribe_id() {
+        let mut buf = [8 << 4 | 0b0010, 8, 0, 1, 0, 3, b'a', b'/', b'b', 1];
+        rewrite_packet_id_in_place(&mut buf, 42).unwrap();
+        match parse(&buf).unwrap().unwrap() {
+            (Packet::Subscribe(subscribe), 10) => {
+                assert_eq!(subscribe.packet_id(), 42);
+                let mut filters = subscribe.filters();
+                assert_eq!(filters.next(), Some(Ok(("a/b", QoS::AtLeastOnce))));
+                assert_eq!(filters.next(), None);
+            }
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn rewrite_without_id() {
+        let mut buf = [3 << 4 | 0b0000, 3, 0, 1, b'a'];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::PacketType));
+
+        let mut buf = [12 << 4, 0];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::PacketType));
+
+        let mut buf = [4 << 4, 2, 0];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::InvalidLength));
+
+        // the identifier lies beyond the remaining length
+        let mut buf = [4 << 4, 0, 0xAA, 0xBB];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::InvalidLength));
+        assert_eq!(buf, [4 << 4, 0, 0xAA, 0xBB]);
+        let mut buf = [3 << 4 | 0b0010, 3, 0, 1, b'a', 0xAA, 0xBB];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn rewrite_reserved_qos() {
+        let mut buf = [3 << 4 | 0b0110, 5, 0, 1, b'a', 0xAA, 0xBB];
+        let result = rewrite_packet_id_in_place(&mut buf, 1);
+        assert_eq!(result, Err(Error::InvalidPublishQoS));
+        assert_eq!(buf[5..], [0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn stats() {
+        let inputs: [(&[u8], PacketType, u32); 4] = [
+            (&[1 << 4 | 0b0000, 12], PacketType::Connect, 12),
+            (&[3 << 4 | 0b0011, 0x80, 0x01], PacketType::Publish, 128),
+            (&[8 << 4 | 0b0010, 5], PacketType::Subscribe, 5),
+            (&[12 << 4 | 0b0000, 0], PacketType::Pingreq, 0),
+        ];
+        for (buf, expected_type, expected_len) in inputs.iter() {
+            let (packet_type, len) = quick_stats(buf).unwrap().unwrap();
+            assert_eq!(packet_type, *expected_type);
+            assert_eq!(len, *expected_len);
+        }
+        assert_eq!(quick_stats(&[1 << 4]).unwrap(), Status::Partial);
     }
 
     #[test]
     fn bad_len() {
-        let result = Header::from_bytes(&[03 << 4 | 0]).unwrap();
+        let result = FixedHeader::from_bytes(&[3 << 4 | 0]).unwrap();
         assert_eq!(result, Status::Partial);
     }
 }
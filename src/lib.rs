@@ -1,14 +1,17 @@
 #![deny(warnings)]
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
 extern crate byteorder;
-#[cfg(feature = "std")]
+#[cfg(any(feature = "std", test))]
 extern crate std as core;
 
 #[cfg(test)]
 extern crate rayon;
 
 use byteorder::{BigEndian, ByteOrder};
+use core::fmt;
 use core::result;
 use core::str;
 
@@ -22,11 +25,65 @@ pub use error::Error;
 pub mod status;
 pub use status::Status;
 
+pub mod encode;
+pub use encode::Encode;
+use encode::EncodeBody;
+
 pub mod header;
-pub use header::Header;
+#[allow(deprecated)]
+pub use header::decode_remaining_length;
+pub use header::{packet_type_of, quick_stats, rewrite_packet_id_in_place, FixedHeader, Header};
 
 pub mod connect;
-pub use connect::Connect;
+pub use connect::{expect_connect, Connect};
+
+pub mod connack;
+pub use connack::{Connack, ConnackReturnCode};
+
+pub mod publish;
+pub use publish::{encode_publish, AckResponse, Publish, PublishHeader, RetainedAction};
+
+pub mod ack;
+pub use ack::{Puback, Pubcomp, Pubrec, Pubrel, Unsuback};
+
+pub mod ping;
+pub use ping::{Pingreq, Pingresp};
+
+pub mod disconnect;
+pub use disconnect::Disconnect;
+
+pub mod packet;
+#[cfg(feature = "alloc")]
+pub use packet::group_publishes_by_topic;
+pub use packet::{parse, parse_bounded, Packet};
+
+pub mod decoder;
+pub use decoder::Decoder;
+#[cfg(feature = "alloc")]
+pub use decoder::PacketBuffer;
+
+pub mod state;
+pub use state::ConnectionState;
+#[cfg(feature = "alloc")]
+pub use state::PendingRequests;
+
+pub mod suback;
+pub use suback::{effective_qos, Suback, SubackReturnCode, SubackReturnCodes};
+
+pub mod unsubscribe;
+pub use unsubscribe::{Unsubscribe, UnsubscribeFilters};
+
+pub mod topic;
+pub use topic::{
+    filter_subsumes, validate_publish_topic, validate_topic_filter, validate_topic_name,
+};
+
+pub mod subscribe;
+#[cfg(feature = "alloc")]
+pub use subscribe::split_subscribe;
+#[cfg(feature = "std")]
+pub use subscribe::write_subscribe;
+pub use subscribe::{Subscribe, SubscribeFilters};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum QoS {
@@ -35,6 +92,70 @@ pub enum QoS {
     ExactlyOnce,
 }
 
+impl QoS {
+    /// Decodes a QoS level, rejecting the reserved value 3 and anything above
+    /// with `Error::InvalidQoS` (MQTT-3.3.1-4).
+    pub fn from_u8(value: u8) -> Result<QoS> {
+        match value {
+            0 => Ok(QoS::AtMostOnce),
+            1 => Ok(QoS::AtLeastOnce),
+            2 => Ok(QoS::ExactlyOnce),
+            _ => Err(Error::InvalidQoS),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => 1,
+            QoS::ExactlyOnce => 2,
+        }
+    }
+}
+
+impl fmt::Display for QoS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            QoS::AtMostOnce => "at most once",
+            QoS::AtLeastOnce => "at least once",
+            QoS::ExactlyOnce => "exactly once",
+        })
+    }
+}
+
+/// Protocol version as given by the protocol level byte of a CONNECT.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ProtocolVersion {
+    /// MQTT 3.1, protocol level 3
+    V3_1,
+    /// MQTT 3.1.1, protocol level 4
+    V3_1_1,
+    /// MQTT 5.0, protocol level 5
+    V5,
+    /// Any other protocol level
+    Unknown(u8),
+}
+
+impl ProtocolVersion {
+    pub fn from_u8(revision: u8) -> ProtocolVersion {
+        match revision {
+            3 => ProtocolVersion::V3_1,
+            4 => ProtocolVersion::V3_1_1,
+            5 => ProtocolVersion::V5,
+            v => ProtocolVersion::Unknown(v),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            ProtocolVersion::V3_1 => 3,
+            ProtocolVersion::V3_1_1 => 4,
+            ProtocolVersion::V5 => 5,
+            ProtocolVersion::Unknown(v) => v,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PacketType {
     Connect,
@@ -53,28 +174,123 @@ pub enum PacketType {
     Disconnect,
 }
 
+impl PacketType {
+    /// Maps the 4 bit MQTT control packet type value to a `PacketType`,
+    /// rejecting the reserved values 0 and 15 with `Error::PacketType`.
+    pub fn from_u8(value: u8) -> Result<PacketType> {
+        match value {
+            1 => Ok(PacketType::Connect),
+            2 => Ok(PacketType::Connack),
+            3 => Ok(PacketType::Publish),
+            4 => Ok(PacketType::Puback),
+            5 => Ok(PacketType::Pubrec),
+            6 => Ok(PacketType::Pubrel),
+            7 => Ok(PacketType::Pubcomp),
+            8 => Ok(PacketType::Subscribe),
+            9 => Ok(PacketType::Suback),
+            10 => Ok(PacketType::Unsubscribe),
+            11 => Ok(PacketType::Unsuback),
+            12 => Ok(PacketType::Pingreq),
+            13 => Ok(PacketType::Pingresp),
+            14 => Ok(PacketType::Disconnect),
+            _ => Err(Error::PacketType),
+        }
+    }
+
+    /// Number of distinct packet types, i.e. one past the largest
+    /// `discriminant`.
+    pub const COUNT: usize = 14;
+
+    /// Returns a stable index in `0..PacketType::COUNT` for the packet type,
+    /// e.g. for indexing an array of per-type counters.
+    ///
+    /// The mapping follows the order of the MQTT control packet type values:
+    /// `Connect` is 0, `Connack` is 1 and so on up to `Disconnect` at 13. It
+    /// will not change between releases.
+    pub fn discriminant(&self) -> usize {
+        match *self {
+            PacketType::Connect => 0,
+            PacketType::Connack => 1,
+            PacketType::Publish => 2,
+            PacketType::Puback => 3,
+            PacketType::Pubrec => 4,
+            PacketType::Pubrel => 5,
+            PacketType::Pubcomp => 6,
+            PacketType::Subscribe => 7,
+            PacketType::Suback => 8,
+            PacketType::Unsubscribe => 9,
+            PacketType::Unsuback => 10,
+            PacketType::Pingreq => 11,
+            PacketType::Pingresp => 12,
+            PacketType::Disconnect => 13,
+        }
+    }
+
+    /// Returns true for packet types which are only valid once a session has
+    /// been established by a CONNECT packet, i.e. every type except CONNECT.
+    ///
+    /// This allows a server to reject out of order packets based on the fixed
+    /// header alone.
+    pub fn requires_authenticated_session(&self) -> bool {
+        *self != PacketType::Connect
+    }
+}
+
 pub type Result<T> = result::Result<T, Error>;
 
-pub fn parse_string(bytes: &[u8]) -> Result<Status<&str>> {
-    // we need at least the 2 bytes to figure out length of the utf-8 encoded
-    // string in bytes
+/// Decodes a variable byte integer as used by the fixed header remaining
+/// length, returning the value and the number of bytes it occupied.
+///
+/// Returns `Status::Partial` when a continuation bit is set on the last
+/// available byte and `Error::RemainingLength` when the encoding runs past
+/// the maximum of four bytes.
+pub fn read_varint(bytes: &[u8]) -> Result<Status<(u32, usize)>> {
+    let mut multiplier = 1;
+    let mut value = 0u32;
+    let mut index = 0;
+
+    loop {
+        if multiplier > 128 * 128 * 128 {
+            break Err(Error::RemainingLength);
+        } else if index >= bytes.len() {
+            break Ok(Status::Partial);
+        }
+
+        let byte = bytes[index];
+        index += 1;
+        value += (byte & 127) as u32 * multiplier;
+        multiplier *= 128;
+        if byte & 128 == 0 {
+            break Ok(Status::Complete((value, index)));
+        }
+    }
+}
+
+pub fn parse_bytes(bytes: &[u8]) -> Result<Status<&[u8]>> {
+    // we need at least the 2 bytes to figure out length of the data
     if bytes.len() < 2 {
         return Ok(Status::Partial);
     }
 
-    let len = BigEndian::read_u16(bytes);
-    if bytes.len() - 2 < len as usize {
+    let len = BigEndian::read_u16(bytes) as usize;
+    if bytes.len() - 2 < len {
         return Ok(Status::Partial);
     }
 
-    let val = if len > 0 {
+    Ok(Status::Complete(&bytes[2..len + 2]))
+}
+
+pub fn parse_string(bytes: &[u8]) -> Result<Status<&str>> {
+    let bytes = complete!(parse_bytes(bytes));
+
+    let val = if !bytes.is_empty() {
         // Rust string slices are never in the code point range 0xD800 and
         // 0xDFFF which takes care of requirement MQTT-1.5.3-1. str::from_utf8
         // will fail if those code points are found in "bytes".
         //
         // Rust utf-8 decoding also takes care of MQTT-1.5.3-3. U+FEFF does not
         // get ignored/stripped off.
-        str::from_utf8(&bytes[2..(len + 2) as usize])?
+        str::from_utf8(bytes)?
     } else {
         ""
     };
@@ -91,9 +307,175 @@ pub fn parse_string(bytes: &[u8]) -> Result<Status<&str>> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::io::{Cursor, Write};
+    use core::ops::{Deref, DerefMut};
+
+    /// Fixed capacity buffer used by the tests to assemble packets without
+    /// requiring an allocator.
+    pub struct Encoded {
+        buf: [u8; 128],
+        len: usize,
+    }
+
+    impl Encoded {
+        pub fn new() -> Encoded {
+            Encoded {
+                buf: [0; 128],
+                len: 0,
+            }
+        }
+
+        pub fn push(&mut self, bytes: &[u8]) {
+            self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            self.len += bytes.len();
+        }
+
+        pub fn push_u16(&mut self, value: u16) {
+            BigEndian::write_u16(&mut self.buf[self.len..], value);
+            self.len += 2;
+        }
 
-    use byteorder::WriteBytesExt;
+        pub fn push_prefixed(&mut self, bytes: &[u8]) {
+            self.push_u16(bytes.len() as u16);
+            self.push(bytes);
+        }
+    }
+
+    impl Deref for Encoded {
+        type Target = [u8];
+
+        fn deref(&self) -> &[u8] {
+            &self.buf[..self.len]
+        }
+    }
+
+    impl DerefMut for Encoded {
+        fn deref_mut(&mut self) -> &mut [u8] {
+            &mut self.buf[..self.len]
+        }
+    }
+
+    /// QoS 1 PUBLISH of "hi" to topic "a" with packet identifier 1.
+    pub const PUBLISH: [u8; 9] = [
+        3 << 4 | 0b0010, // PacketType::Publish, QoS 1
+        7,               // remaining length
+        0,
+        1,
+        b'a', // topic
+        0,
+        1, // packet id
+        b'h',
+        b'i', // payload
+    ];
+
+    #[test]
+    fn qos_round_trip() {
+        for value in 0..3 {
+            assert_eq!(QoS::from_u8(value).unwrap().to_u8(), value);
+        }
+        assert_eq!(QoS::from_u8(3), Err(Error::InvalidQoS));
+        assert_eq!(QoS::from_u8(0xFF), Err(Error::InvalidQoS));
+    }
+
+    #[test]
+    fn qos_display() {
+        assert_eq!(QoS::AtMostOnce.to_string(), "at most once");
+        assert_eq!(QoS::AtLeastOnce.to_string(), "at least once");
+        assert_eq!(QoS::ExactlyOnce.to_string(), "exactly once");
+    }
+
+    #[test]
+    fn protocol_version() {
+        let inputs = [
+            (3, ProtocolVersion::V3_1),
+            (4, ProtocolVersion::V3_1_1),
+            (5, ProtocolVersion::V5),
+            (6, ProtocolVersion::Unknown(6)),
+        ];
+        for &(revision, version) in inputs.iter() {
+            assert_eq!(ProtocolVersion::from_u8(revision), version);
+            assert_eq!(version.to_u8(), revision);
+        }
+    }
+
+    #[test]
+    fn packet_type_from_u8() {
+        for value in 1..15 {
+            let packet_type = PacketType::from_u8(value).unwrap();
+            assert_eq!(packet_type.discriminant(), value as usize - 1);
+        }
+        assert_eq!(PacketType::from_u8(0), Err(Error::PacketType));
+        assert_eq!(PacketType::from_u8(15), Err(Error::PacketType));
+    }
+
+    #[test]
+    fn packet_type_requires_session() {
+        assert!(PacketType::Publish.requires_authenticated_session());
+        assert!(PacketType::Disconnect.requires_authenticated_session());
+        assert!(!PacketType::Connect.requires_authenticated_session());
+    }
+
+    #[test]
+    fn packet_type_discriminant() {
+        let types = [
+            PacketType::Connect,
+            PacketType::Connack,
+            PacketType::Publish,
+            PacketType::Puback,
+            PacketType::Pubrec,
+            PacketType::Pubrel,
+            PacketType::Pubcomp,
+            PacketType::Subscribe,
+            PacketType::Suback,
+            PacketType::Unsubscribe,
+            PacketType::Unsuback,
+            PacketType::Pingreq,
+            PacketType::Pingresp,
+            PacketType::Disconnect,
+        ];
+
+        let mut seen = [false; PacketType::COUNT];
+        for packet_type in types.iter() {
+            let index = packet_type.discriminant();
+            assert!(index < PacketType::COUNT);
+            assert!(!seen[index]);
+            seen[index] = true;
+        }
+        assert!(seen.iter().all(|&v| v));
+    }
+
+    #[test]
+    fn varint() {
+        let inputs: [(&[u8], u32, usize); 6] = [
+            (&[0x00], 0, 1),
+            (&[0x7F], 127, 1),
+            (&[0x80, 0x01], 128, 2),
+            (&[0xFF, 0x7F], 16_383, 2),
+            (&[0x80, 0x80, 0x01], 16_384, 3),
+            (&[0xFF, 0xFF, 0xFF, 0x7F], 268_435_455, 4),
+        ];
+        for (buf, value, len) in inputs.iter() {
+            assert_eq!(read_varint(buf), Ok(Status::Complete((*value, *len))));
+        }
+    }
+
+    #[test]
+    fn varint_trailing_bytes() {
+        let buf = [0x80, 0x01, 0xFF];
+        assert_eq!(read_varint(&buf), Ok(Status::Complete((128, 2))));
+    }
+
+    #[test]
+    fn varint_partial() {
+        assert_eq!(read_varint(&[]), Ok(Status::Partial));
+        assert_eq!(read_varint(&[0x80]), Ok(Status::Partial));
+        assert_eq!(read_varint(&[0xFF, 0xFF, 0xFF]), Ok(Status::Partial));
+    }
+
+    #[test]
+    fn varint_overflow() {
+        let buf = [0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        assert_eq!(read_varint(&buf), Err(Error::RemainingLength));
+    }
 
     #[test]
     fn small_buffer() {
@@ -112,33 +494,56 @@ mod tests {
         assert_eq!(Status::Complete(""), parse_string(&buf).unwrap());
     }
 
+    #[test]
+    fn parse_binary() {
+        let inp = [0, 159, 146, 150];
+        let buf = length_prefixed(&inp);
+        assert_eq!(Status::Complete(&inp[..]), parse_bytes(&buf).unwrap());
+        assert_eq!(Status::Partial, parse_bytes(&buf[..3]).unwrap());
+    }
+
     #[test]
     fn parse_str() {
         let inp = "don't panic!";
-        let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
-        buf.write(inp.as_bytes()).unwrap();
-        assert_eq!(
-            Status::Complete(inp),
-            parse_string(buf.get_ref().as_ref()).unwrap()
-        );
+        let buf = length_prefixed(inp.as_bytes());
+        assert_eq!(Status::Complete(inp), parse_string(&buf).unwrap());
     }
 
     #[test]
     fn invalid_utf8() {
-        let inp = [0, 159, 146, 150];
-        let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
-        buf.write(&inp).unwrap();
-        assert_eq!(Err(Error::Utf8), parse_string(buf.get_ref().as_ref()));
+        let buf = length_prefixed(&[0, 159, 146, 150]);
+        assert_eq!(Err(Error::Utf8), parse_string(&buf));
+    }
+
+    fn length_prefixed(inp: &[u8]) -> Encoded {
+        let mut buf = Encoded::new();
+        buf.push_prefixed(inp);
+        buf
+    }
+
+    #[test]
+    fn lone_continuation_utf8() {
+        let buf = length_prefixed(&[b'a', 0x80, b'b']);
+        assert_eq!(Err(Error::Utf8), parse_string(&buf));
+    }
+
+    #[test]
+    fn overlong_utf8() {
+        // '/' encoded in two bytes instead of one
+        let buf = length_prefixed(&[0xC0, 0xAF]);
+        assert_eq!(Err(Error::Utf8), parse_string(&buf));
+    }
+
+    #[test]
+    fn surrogate_utf8() {
+        // U+D800 encoded as if it were a regular code point
+        let buf = length_prefixed(&[0xED, 0xA0, 0x80]);
+        assert_eq!(Err(Error::Utf8), parse_string(&buf));
     }
 
     #[test]
     fn null_utf8() {
-        let inp = format!("don't {} panic!", '\u{0000}');
-        let mut buf = Cursor::new(Vec::new());
-        buf.write_u16::<BigEndian>(inp.len() as u16).unwrap();
-        buf.write(inp.as_bytes()).unwrap();
-        assert_eq!(Err(Error::Utf8), parse_string(buf.get_ref().as_ref()));
+        let buf = length_prefixed(b"don't \0 panic!");
+        assert_eq!(Err(Error::Utf8), parse_string(&buf));
     }
 }
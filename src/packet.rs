@@ -0,0 +1,617 @@
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::topic::{validate_topic_filter, validate_topic_name};
+use super::{
+    Connack, Connect, Disconnect, Encode, EncodeBody, Error, FixedHeader, PacketType, Pingreq,
+    Pingresp, ProtocolVersion, Puback, Pubcomp, Publish, Pubrec, Pubrel, QoS, Result, Status,
+    Suback, Subscribe, Unsuback, Unsubscribe,
+};
+
+/// Any decoded MQTT control packet.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Packet<'buf> {
+    Connect(Connect<'buf>),
+    Connack(Connack),
+    Publish(Publish<'buf>),
+    Puback(Puback),
+    Pubrec(Pubrec),
+    Pubrel(Pubrel),
+    Pubcomp(Pubcomp),
+    Subscribe(Subscribe<'buf>),
+    Suback(Suback<'buf>),
+    Unsubscribe(Unsubscribe<'buf>),
+    Unsuback(Unsuback),
+    Pingreq(Pingreq),
+    Pingresp(Pingresp),
+    Disconnect(Disconnect),
+}
+
+impl<'buf> Packet<'buf> {
+    pub fn packet_type(&self) -> PacketType {
+        match *self {
+            Packet::Connect(_) => PacketType::Connect,
+            Packet::Connack(_) => PacketType::Connack,
+            Packet::Publish(_) => PacketType::Publish,
+            Packet::Puback(_) => PacketType::Puback,
+            Packet::Pubrec(_) => PacketType::Pubrec,
+            Packet::Pubrel(_) => PacketType::Pubrel,
+            Packet::Pubcomp(_) => PacketType::Pubcomp,
+            Packet::Subscribe(_) => PacketType::Subscribe,
+            Packet::Suback(_) => PacketType::Suback,
+            Packet::Unsubscribe(_) => PacketType::Unsubscribe,
+            Packet::Unsuback(_) => PacketType::Unsuback,
+            Packet::Pingreq(_) => PacketType::Pingreq,
+            Packet::Pingresp(_) => PacketType::Pingresp,
+            Packet::Disconnect(_) => PacketType::Disconnect,
+        }
+    }
+
+    /// Returns the stable index of the packet type in
+    /// `0..PacketType::COUNT`, see `PacketType::discriminant`.
+    pub fn discriminant(&self) -> usize {
+        self.packet_type().discriminant()
+    }
+
+    /// Compares two packets field by field, except for their packet
+    /// identifiers.
+    ///
+    /// This suits packets passed through a bridge which remaps identifiers.
+    /// A PUBLISH still needs an identifier on both sides or on neither, as
+    /// follows from its QoS.
+    pub fn eq_ignoring_packet_id(&self, other: &Packet) -> bool {
+        match (*self, *other) {
+            (Packet::Publish(a), Packet::Publish(b)) => {
+                a.dup() == b.dup()
+                    && a.qos() == b.qos()
+                    && a.retain() == b.retain()
+                    && a.topic() == b.topic()
+                    && a.payload() == b.payload()
+            }
+            (Packet::Puback(_), Packet::Puback(_))
+            | (Packet::Pubrec(_), Packet::Pubrec(_))
+            | (Packet::Pubrel(_), Packet::Pubrel(_))
+            | (Packet::Pubcomp(_), Packet::Pubcomp(_))
+            | (Packet::Unsuback(_), Packet::Unsuback(_)) => true,
+            (Packet::Subscribe(a), Packet::Subscribe(b)) => a.filters().eq(b.filters()),
+            (Packet::Suback(a), Packet::Suback(b)) => a.return_codes().eq(b.return_codes()),
+            (Packet::Unsubscribe(a), Packet::Unsubscribe(b)) => a.filters().eq(b.filters()),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Returns `true` for packets received by a broker which change the
+    /// state it keeps for a persistent session: SUBSCRIBE, UNSUBSCRIBE,
+    /// QoS 1 and 2 PUBLISH packets and the PUBLISH acknowledgements.
+    ///
+    /// Everything else, such as PINGREQ or a QoS 0 PUBLISH, is transient.
+    pub fn affects_session_state(&self) -> bool {
+        match *self {
+            Packet::Publish(ref publish) => publish.qos() != QoS::AtMostOnce,
+            Packet::Puback(_)
+            | Packet::Pubrec(_)
+            | Packet::Pubrel(_)
+            | Packet::Pubcomp(_)
+            | Packet::Subscribe(_)
+            | Packet::Unsubscribe(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Checks the packet against the rules of `version` that decoding leaves
+    /// to the caller, as a single compliance gate for a broker.
+    ///
+    /// For MQTT 3.1.1 a CONNECT is checked as by
+    /// `Connect::from_bytes_validated`, the topic of a PUBLISH with
+    /// `validate_topic_name` and the filters of a SUBSCRIBE or UNSUBSCRIBE
+    /// with `validate_topic_filter`, returning their errors. Any other
+    /// version results in `Error::UnsupportedForVersion`.
+    pub fn validate(&self, version: ProtocolVersion) -> Result<()> {
+        if version != ProtocolVersion::V3_1_1 {
+            return Err(Error::UnsupportedForVersion);
+        }
+
+        match *self {
+            Packet::Connect(ref connect) => connect.check_validated(),
+            Packet::Publish(ref publish) => validate_topic_name(publish.topic()),
+            Packet::Subscribe(ref subscribe) => {
+                for filter in subscribe.filters() {
+                    validate_topic_filter(filter?.0)?;
+                }
+                Ok(())
+            }
+            Packet::Unsubscribe(ref unsubscribe) => {
+                for filter in unsubscribe.filters() {
+                    validate_topic_filter(filter?)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Encodes the packet for `version` into `buf` like `Encode::encode`,
+    /// returning the number of bytes written.
+    ///
+    /// Only MQTT 3.1.1 packets can be encoded, any other version results in
+    /// `Error::UnsupportedForVersion`.
+    pub fn to_bytes_version(&self, version: ProtocolVersion, buf: &mut [u8]) -> Result<usize> {
+        if version != ProtocolVersion::V3_1_1 {
+            return Err(Error::UnsupportedForVersion);
+        }
+
+        self.encode(buf)
+    }
+}
+
+impl<'buf> EncodeBody for Packet<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        match *self {
+            Packet::Connect(ref packet) => packet.fixed_header_byte(),
+            Packet::Connack(ref packet) => packet.fixed_header_byte(),
+            Packet::Publish(ref packet) => packet.fixed_header_byte(),
+            Packet::Puback(ref packet) => packet.fixed_header_byte(),
+            Packet::Pubrec(ref packet) => packet.fixed_header_byte(),
+            Packet::Pubrel(ref packet) => packet.fixed_header_byte(),
+            Packet::Pubcomp(ref packet) => packet.fixed_header_byte(),
+            Packet::Subscribe(ref packet) => packet.fixed_header_byte(),
+            Packet::Suback(ref packet) => packet.fixed_header_byte(),
+            Packet::Unsubscribe(ref packet) => packet.fixed_header_byte(),
+            Packet::Unsuback(ref packet) => packet.fixed_header_byte(),
+            Packet::Pingreq(ref packet) => packet.fixed_header_byte(),
+            Packet::Pingresp(ref packet) => packet.fixed_header_byte(),
+            Packet::Disconnect(ref packet) => packet.fixed_header_byte(),
+        }
+    }
+
+    fn body_len(&self) -> usize {
+        match *self {
+            Packet::Connect(ref packet) => packet.body_len(),
+            Packet::Connack(ref packet) => packet.body_len(),
+            Packet::Publish(ref packet) => packet.body_len(),
+            Packet::Puback(ref packet) => packet.body_len(),
+            Packet::Pubrec(ref packet) => packet.body_len(),
+            Packet::Pubrel(ref packet) => packet.body_len(),
+            Packet::Pubcomp(ref packet) => packet.body_len(),
+            Packet::Subscribe(ref packet) => packet.body_len(),
+            Packet::Suback(ref packet) => packet.body_len(),
+            Packet::Unsubscribe(ref packet) => packet.body_len(),
+            Packet::Unsuback(ref packet) => packet.body_len(),
+            Packet::Pingreq(ref packet) => packet.body_len(),
+            Packet::Pingresp(ref packet) => packet.body_len(),
+            Packet::Disconnect(ref packet) => packet.body_len(),
+        }
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        match *self {
+            Packet::Connect(ref packet) => packet.encode_body(buf),
+            Packet::Connack(ref packet) => packet.encode_body(buf),
+            Packet::Publish(ref packet) => packet.encode_body(buf),
+            Packet::Puback(ref packet) => packet.encode_body(buf),
+            Packet::Pubrec(ref packet) => packet.encode_body(buf),
+            Packet::Pubrel(ref packet) => packet.encode_body(buf),
+            Packet::Pubcomp(ref packet) => packet.encode_body(buf),
+            Packet::Subscribe(ref packet) => packet.encode_body(buf),
+            Packet::Suback(ref packet) => packet.encode_body(buf),
+            Packet::Unsubscribe(ref packet) => packet.encode_body(buf),
+            Packet::Unsuback(ref packet) => packet.encode_body(buf),
+            Packet::Pingreq(ref packet) => packet.encode_body(buf),
+            Packet::Pingresp(ref packet) => packet.encode_body(buf),
+            Packet::Disconnect(ref packet) => packet.encode_body(buf),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match *self {
+            Packet::Connect(ref packet) => packet.validate(),
+            Packet::Connack(ref packet) => packet.validate(),
+            Packet::Publish(ref packet) => packet.validate(),
+            Packet::Puback(ref packet) => packet.validate(),
+            Packet::Pubrec(ref packet) => packet.validate(),
+            Packet::Pubrel(ref packet) => packet.validate(),
+            Packet::Pubcomp(ref packet) => packet.validate(),
+            Packet::Subscribe(ref packet) => packet.validate(),
+            Packet::Suback(ref packet) => packet.validate(),
+            Packet::Unsubscribe(ref packet) => packet.validate(),
+            Packet::Unsuback(ref packet) => packet.validate(),
+            Packet::Pingreq(ref packet) => packet.validate(),
+            Packet::Pingresp(ref packet) => packet.validate(),
+            Packet::Disconnect(ref packet) => packet.validate(),
+        }
+    }
+}
+
+/// Decodes the packet at the start of `bytes`, returning it together with the
+/// total number of bytes it occupies (fixed header included), i.e. the offset
+/// of the next packet.
+///
+/// Returns `Status::Partial` until the whole packet as announced by the
+/// remaining length is available. A packet body that does not match its
+/// remaining length results in `Error::InvalidLength`.
+pub fn parse(bytes: &[u8]) -> Result<Status<(Packet<'_>, usize)>> {
+    parse_bounded(bytes, 268_435_455)
+}
+
+/// Same as `parse` but fails with `Error::PacketTooLarge` as soon as the
+/// fixed header declares a remaining length above `max_remaining_length`,
+/// without waiting for the packet body.
+pub fn parse_bounded(
+    bytes: &[u8],
+    max_remaining_length: u32,
+) -> Result<Status<(Packet<'_>, usize)>> {
+    let (header, read) = complete!(FixedHeader::from_bytes_bounded(bytes, max_remaining_length));
+    let len = header.remaining_length() as usize;
+    if bytes.len() - read < len {
+        return Ok(Status::Partial);
+    }
+    let body = &bytes[read..read + len];
+
+    let packet = match header.packet_type() {
+        PacketType::Connect => {
+            let (connect, consumed) = body_of(Connect::from_bytes_consumed(body)?)?;
+            if consumed != body.len() {
+                return Err(Error::InvalidLength);
+            }
+            Packet::Connect(connect)
+        }
+        PacketType::Connack => Packet::Connack(body_of(Connack::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Publish => {
+            Packet::Publish(body_of(Publish::from_bytes(header.flags(), body)?)?)
+        }
+        PacketType::Puback => Packet::Puback(body_of(Puback::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Pubrec => Packet::Pubrec(body_of(Pubrec::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Pubrel => Packet::Pubrel(body_of(Pubrel::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Pubcomp => Packet::Pubcomp(body_of(Pubcomp::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Subscribe => Packet::Subscribe(body_of(Subscribe::from_bytes(body)?)?),
+        PacketType::Suback => Packet::Suback(body_of(Suback::from_bytes(body)?)?),
+        PacketType::Unsubscribe => Packet::Unsubscribe(body_of(Unsubscribe::from_bytes(body)?)?),
+        PacketType::Unsuback => Packet::Unsuback(body_of(Unsuback::from_bytes(exact(body, 2)?)?)?),
+        PacketType::Pingreq => Packet::Pingreq(body_of(Pingreq::from_bytes(exact(body, 0)?)?)?),
+        PacketType::Pingresp => Packet::Pingresp(body_of(Pingresp::from_bytes(exact(body, 0)?)?)?),
+        PacketType::Disconnect => {
+            Packet::Disconnect(body_of(Disconnect::from_bytes(exact(body, 0)?)?)?)
+        }
+    };
+
+    Ok(Status::Complete((packet, read + len)))
+}
+
+/// Decodes every packet in `bytes` and collects the payloads of the PUBLISH
+/// packets under their topic, in the order they appear. The map borrows
+/// topics and payloads from `bytes`.
+///
+/// `bytes` must end with a complete packet, otherwise
+/// `Error::InvalidLength` is returned.
+#[cfg(feature = "alloc")]
+pub fn group_publishes_by_topic(bytes: &[u8]) -> Result<BTreeMap<&str, Vec<&[u8]>>> {
+    let mut groups: BTreeMap<&str, Vec<&[u8]>> = BTreeMap::new();
+    let mut read = 0;
+    while read < bytes.len() {
+        let (packet, len) = body_of(parse(&bytes[read..])?)?;
+        if let Packet::Publish(publish) = packet {
+            groups
+                .entry(publish.topic())
+                .or_default()
+                .push(publish.payload());
+        }
+        read += len;
+    }
+
+    Ok(groups)
+}
+
+/// The body handed to a packet parser is complete, so running out of bytes
+/// means the remaining length was too short for the packet.
+fn body_of<T>(status: Status<T>) -> Result<T> {
+    match status {
+        Status::Complete(v) => Ok(v),
+        Status::Partial => Err(Error::InvalidLength),
+    }
+}
+
+/// Checks the body of a packet with a fixed size variable header.
+fn exact(body: &[u8], len: usize) -> Result<&[u8]> {
+    if body.len() != len {
+        return Err(Error::InvalidLength);
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::PUBLISH;
+
+    const CONNECT: [u8; 20] = [
+        1 << 4, // PacketType::Connect
+        18,     // remaining length
+        0,
+        4,
+        b'M',
+        b'Q',
+        b'T',
+        b'T', // protocol name
+        4,    // protocol revision
+        0b0000_0010,
+        0,
+        60, // keep alive
+        0,
+        6,
+        b'c',
+        b'l',
+        b'i',
+        b'e',
+        b'n',
+        b't', // client id
+    ];
+
+    #[test]
+    fn successive_packets() {
+        let mut buf = CONNECT.to_vec();
+        buf.extend_from_slice(&PUBLISH);
+
+        let (packet, read) = parse(&buf).unwrap().unwrap();
+        assert_eq!(read, CONNECT.len());
+        match packet {
+            Packet::Connect(connect) => assert_eq!(connect.client_id(), "client"),
+            _ => panic!("expected a connect packet"),
+        }
+
+        let (packet, read) = parse(&buf[read..]).unwrap().unwrap();
+        assert_eq!(read, PUBLISH.len());
+        match packet {
+            Packet::Publish(publish) => {
+                assert_eq!(publish.topic(), "a");
+                assert_eq!(publish.qos(), QoS::AtLeastOnce);
+                assert_eq!(publish.packet_id(), Some(1));
+                assert_eq!(publish.payload(), b"hi");
+            }
+            _ => panic!("expected a publish packet"),
+        }
+    }
+
+    #[test]
+    fn partial() {
+        for len in 0..PUBLISH.len() {
+            assert_eq!(parse(&PUBLISH[..len]).unwrap(), Status::Partial);
+        }
+    }
+
+    #[test]
+    fn empty_packets() {
+        let inputs = [
+            ([0xC0, 0], PacketType::Pingreq),
+            ([0xD0, 0], PacketType::Pingresp),
+            ([0xE0, 0], PacketType::Disconnect),
+        ];
+        for (buf, packet_type) in inputs.iter() {
+            let (packet, read) = parse(buf).unwrap().unwrap();
+            assert_eq!(packet.packet_type(), *packet_type);
+            assert_eq!(read, 2);
+        }
+    }
+
+    #[test]
+    fn acks() {
+        let inputs = [
+            ([0x20, 2, 0, 0], PacketType::Connack),
+            ([0x40, 2, 0, 1], PacketType::Puback),
+            ([0x50, 2, 0, 1], PacketType::Pubrec),
+            ([0x62, 2, 0, 1], PacketType::Pubrel),
+            ([0x70, 2, 0, 1], PacketType::Pubcomp),
+            ([0xB0, 2, 0, 1], PacketType::Unsuback),
+        ];
+        for (buf, packet_type) in inputs.iter() {
+            let (packet, read) = parse(buf).unwrap().unwrap();
+            assert_eq!(packet.packet_type(), *packet_type);
+            assert_eq!(read, 4);
+        }
+    }
+
+    #[test]
+    fn bad_remaining_length() {
+        // PINGREQ with a body
+        assert_eq!(parse(&[0xC0, 1, 0]), Err(Error::InvalidLength));
+        // PUBACK with a one byte body
+        assert_eq!(parse(&[0x40, 1, 0]), Err(Error::InvalidLength));
+        // CONNECT with trailing bytes after the payload
+        let mut buf = CONNECT.to_vec();
+        buf[1] += 1;
+        buf.push(0);
+        assert_eq!(parse(&buf), Err(Error::InvalidLength));
+        // CONNECT remaining length ending inside the payload
+        let mut buf = CONNECT.to_vec();
+        buf[1] -= 1;
+        assert_eq!(parse(&buf), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn bounded() {
+        let (packet, _) = parse_bounded(&PUBLISH, 7).unwrap().unwrap();
+        assert_eq!(packet.packet_type(), PacketType::Publish);
+        assert_eq!(parse_bounded(&PUBLISH, 6), Err(Error::PacketTooLarge));
+        // rejected from the fixed header alone
+        assert_eq!(parse_bounded(&PUBLISH[..2], 6), Err(Error::PacketTooLarge));
+    }
+
+    #[test]
+    fn discriminant() {
+        let inputs: [&[u8]; 14] = [
+            &CONNECT,
+            &[0x20, 2, 0, 0],
+            &PUBLISH,
+            &[0x40, 2, 0, 1],
+            &[0x50, 2, 0, 1],
+            &[0x62, 2, 0, 1],
+            &[0x70, 2, 0, 1],
+            &[0x82, 6, 0, 1, 0, 1, b'a', 1],
+            &[0x90, 3, 0, 1, 0],
+            &[0xA2, 5, 0, 1, 0, 1, b'a'],
+            &[0xB0, 2, 0, 1],
+            &[0xC0, 0],
+            &[0xD0, 0],
+            &[0xE0, 0],
+        ];
+        let mut seen = [false; PacketType::COUNT];
+        for buf in inputs.iter() {
+            let (packet, _) = parse(buf).unwrap().unwrap();
+            let index = packet.discriminant();
+            assert!(index < PacketType::COUNT);
+            assert!(!seen[index]);
+            seen[index] = true;
+        }
+    }
+
+    #[test]
+    fn eq_ignoring_packet_id() {
+        let mut other = PUBLISH;
+        other[6] = 2;
+        let (a, _) = parse(&PUBLISH).unwrap().unwrap();
+        let (b, _) = parse(&other).unwrap().unwrap();
+        assert_ne!(a, b);
+        assert!(a.eq_ignoring_packet_id(&b));
+
+        other[8] = b'o';
+        let (b, _) = parse(&other).unwrap().unwrap();
+        assert!(!a.eq_ignoring_packet_id(&b));
+
+        let (a, _) = parse(&[0x40, 2, 0, 1]).unwrap().unwrap();
+        let (b, _) = parse(&[0x40, 2, 0, 2]).unwrap().unwrap();
+        let (c, _) = parse(&[0x50, 2, 0, 1]).unwrap().unwrap();
+        assert!(a.eq_ignoring_packet_id(&b));
+        assert!(!a.eq_ignoring_packet_id(&c));
+    }
+
+    #[test]
+    fn session_state() {
+        let subscribe = [0x82, 6, 0, 1, 0, 1, b'a', 1];
+        let (packet, _) = parse(&subscribe).unwrap().unwrap();
+        assert!(packet.affects_session_state());
+
+        let (packet, _) = parse(&[0xC0, 0]).unwrap().unwrap();
+        assert!(!packet.affects_session_state());
+
+        let (packet, _) = parse(&PUBLISH).unwrap().unwrap();
+        assert!(packet.affects_session_state());
+        let (packet, _) = parse(&[0x30, 3, 0, 1, b'a']).unwrap().unwrap();
+        assert!(!packet.affects_session_state());
+    }
+
+    #[test]
+    fn encode_invalid_connect() {
+        let mut buf = [0u8; 64];
+        let connect = Connect::new("client").with_password(b"secret");
+        assert_eq!(
+            Packet::Connect(connect).encode(&mut buf),
+            Err(Error::PasswordWithoutUsername)
+        );
+
+        let long = [b'a'; u16::MAX as usize + 1];
+        let connect = Connect::new(core::str::from_utf8(&long).unwrap());
+        assert_eq!(
+            Packet::Connect(connect).encode(&mut buf),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let inputs: [&[u8]; 13] = [
+            &CONNECT,
+            &PUBLISH,
+            &[0x20, 2, 1, 0],
+            &[0x40, 2, 0, 1],
+            &[0x50, 2, 0, 1],
+            &[0x62, 2, 0, 1],
+            &[0x70, 2, 0, 1],
+            &[0x82, 6, 0, 1, 0, 1, b'a', 1],
+            &[0x90, 4, 0, 1, 0, 0x80],
+            &[0xA2, 5, 0, 1, 0, 1, b'a'],
+            &[0xB0, 2, 0, 1],
+            &[0xC0, 0],
+            &[0xE0, 0],
+        ];
+        for buf in inputs.iter() {
+            let (packet, _) = parse(buf).unwrap().unwrap();
+            let mut out = [0u8; 32];
+            let len = packet.encode(&mut out).unwrap();
+            assert_eq!(len, packet.encoded_len());
+            assert_eq!(&out[..len], *buf);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn group_publishes() {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&PUBLISH);
+        buf.extend_from_slice(&[0xc0, 0]);
+        buf.extend_from_slice(&[0x30, 5, 0, 1, b'b', b'y', b'o']);
+        buf.extend_from_slice(&PUBLISH);
+
+        let groups = group_publishes_by_topic(&buf).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups["a"], [&b"hi"[..], &b"hi"[..]]);
+        assert_eq!(groups["b"], [&b"yo"[..]]);
+
+        let result = group_publishes_by_topic(&buf[..buf.len() - 1]);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn validate() {
+        let (connect, _) = parse(&CONNECT).unwrap().unwrap();
+        let (publish, _) = parse(&PUBLISH).unwrap().unwrap();
+        let (subscribe, _) = parse(&[0x82, 6, 0, 1, 0, 1, b'a', 1]).unwrap().unwrap();
+        for packet in [connect, publish, subscribe, Packet::Pingreq(Pingreq)].iter() {
+            assert_eq!(packet.validate(ProtocolVersion::V3_1_1), Ok(()));
+            for &version in [ProtocolVersion::V3_1, ProtocolVersion::V5].iter() {
+                assert_eq!(packet.validate(version), Err(Error::UnsupportedForVersion));
+            }
+        }
+
+        let mut buf = CONNECT;
+        buf[4..8].copy_from_slice(b"HTTP");
+        let (packet, _) = parse(&buf).unwrap().unwrap();
+        assert_eq!(
+            packet.validate(ProtocolVersion::V3_1_1),
+            Err(Error::InvalidProtocolName)
+        );
+
+        // clean session off and a zero length client id
+        let mut buf = CONNECT;
+        buf[1] = 12;
+        buf[9] = 0b0000_0000;
+        buf[13] = 0;
+        let (packet, _) = parse(&buf[..14]).unwrap().unwrap();
+        assert_eq!(
+            packet.validate(ProtocolVersion::V3_1_1),
+            Err(Error::EmptyClientId)
+        );
+
+        // a requested QoS above 2 only shows when iterating the filters
+        let (packet, _) = parse(&[0x82, 6, 0, 1, 0, 1, b'a', 3]).unwrap().unwrap();
+        assert_eq!(
+            packet.validate(ProtocolVersion::V3_1_1),
+            Err(Error::InvalidQoS)
+        );
+    }
+
+    #[test]
+    fn to_bytes_version() {
+        let (packet, _) = parse(&PUBLISH).unwrap().unwrap();
+        let mut buf = [0u8; 16];
+        let len = packet
+            .to_bytes_version(ProtocolVersion::V3_1_1, &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..len], &PUBLISH[..]);
+        assert_eq!(
+            packet.to_bytes_version(ProtocolVersion::V5, &mut buf),
+            Err(Error::UnsupportedForVersion)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use super::{Encode, EncodeBody, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Pingreq;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Pingresp;
+
+impl Pingreq {
+    /// PINGREQ has no variable header or payload, so there is nothing to
+    /// decode after the fixed header.
+    pub fn from_bytes(_bytes: &[u8]) -> Result<Status<Pingreq>> {
+        Ok(Status::Complete(Pingreq))
+    }
+
+    /// Writes the two byte PINGREQ packet into `buf`, returning the number of
+    /// bytes written.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize> {
+        self.encode(buf)
+    }
+}
+
+impl Pingresp {
+    /// Like PINGREQ, a PINGRESP has no body to decode.
+    pub fn from_bytes(_bytes: &[u8]) -> Result<Status<Pingresp>> {
+        Ok(Status::Complete(Pingresp))
+    }
+
+    /// Writes the two byte PINGRESP packet into `buf`, returning the number
+    /// of bytes written.
+    pub fn to_bytes(&self, buf: &mut [u8]) -> Result<usize> {
+        self.encode(buf)
+    }
+}
+
+impl EncodeBody for Pingreq {
+    fn fixed_header_byte(&self) -> u8 {
+        12 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        0
+    }
+
+    fn encode_body(&self, _buf: &mut [u8]) {}
+}
+
+impl EncodeBody for Pingresp {
+    fn fixed_header_byte(&self) -> u8 {
+        13 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        0
+    }
+
+    fn encode_body(&self, _buf: &mut [u8]) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Error;
+
+    #[test]
+    fn pingreq() {
+        let mut buf = [0xFFu8; 3];
+        assert_eq!(Pingreq.to_bytes(&mut buf), Ok(2));
+        assert_eq!(buf[..2], [0xC0, 0x00]);
+        assert_eq!(Pingreq::from_bytes(&[]).unwrap(), Status::Complete(Pingreq));
+    }
+
+    #[test]
+    fn pingresp() {
+        let mut buf = [0xFFu8; 2];
+        assert_eq!(Pingresp.to_bytes(&mut buf), Ok(2));
+        assert_eq!(buf, [0xD0, 0x00]);
+        assert_eq!(
+            Pingresp::from_bytes(&[]).unwrap(),
+            Status::Complete(Pingresp)
+        );
+    }
+
+    #[test]
+    fn small_buffer() {
+        let mut buf = [0u8; 1];
+        assert_eq!(Pingreq.to_bytes(&mut buf), Err(Error::InvalidLength));
+        assert_eq!(Pingresp.to_bytes(&mut buf), Err(Error::InvalidLength));
+    }
+}
@@ -0,0 +1,584 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::topic::validate_publish_topic;
+use super::{
+    parse_string, Encode, EncodeBody, Error, PacketId, PacketTypeFlags, ProtocolVersion, Puback,
+    Pubrec, QoS, Result, Status,
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Publish<'buf> {
+    dup: bool,
+    qos: QoS,
+    retain: bool,
+    topic: &'buf str,
+    packet_id: Option<PacketId>,
+    payload: &'buf [u8],
+}
+
+impl<'buf> Publish<'buf> {
+    /// Creates a PUBLISH to be encoded with `Encode`.
+    ///
+    /// The arguments are checked as described for `encode_publish`.
+    pub fn new(
+        topic: &'buf str,
+        payload: &'buf [u8],
+        qos: QoS,
+        packet_id: Option<PacketId>,
+        retain: bool,
+        dup: bool,
+    ) -> Result<Publish<'buf>> {
+        validate_publish_topic(topic, ProtocolVersion::V3_1_1)?;
+
+        if dup && qos == QoS::AtMostOnce {
+            return Err(Error::InvalidPublishFlags);
+        }
+
+        let packet_id = match (qos, packet_id) {
+            (QoS::AtMostOnce, None) => None,
+            (QoS::AtMostOnce, Some(_)) | (_, None) | (_, Some(0)) => {
+                return Err(Error::InvalidPacketId)
+            }
+            (_, Some(id)) => Some(id),
+        };
+
+        Ok(Publish {
+            dup,
+            qos,
+            retain,
+            topic,
+            packet_id,
+            payload,
+        })
+    }
+
+    /// Encodes a QoS 1 PUBLISH without DUP and RETAIN into `buf`, returning
+    /// the number of bytes written.
+    ///
+    /// This is the common case of `encode_publish` and fails the same way.
+    pub fn qos1(topic: &str, payload: &[u8], packet_id: PacketId, buf: &mut [u8]) -> Result<usize> {
+        encode_publish(
+            topic,
+            payload,
+            QoS::AtLeastOnce,
+            Some(packet_id),
+            false,
+            false,
+            buf,
+        )
+    }
+
+    /// Decodes a PUBLISH packet from `bytes`, which must hold exactly the
+    /// remaining length of the packet. `flags` is the flag nibble from the
+    /// fixed header carrying the DUP, QoS and RETAIN values.
+    ///
+    /// A topic name rejected by `validate_publish_topic` for MQTT 3.1.1
+    /// results in `Error::InvalidTopicName`.
+    pub fn from_bytes(flags: PacketTypeFlags, bytes: &'buf [u8]) -> Result<Status<Publish<'buf>>> {
+        let (header, read) = complete!(PublishHeader::from_bytes(flags, bytes));
+
+        Ok(Status::Complete(Publish {
+            dup: header.dup,
+            qos: header.qos,
+            retain: header.retain,
+            topic: header.topic,
+            packet_id: header.packet_id,
+            payload: &bytes[read..],
+        }))
+    }
+
+    /// Decodes only the variable header of a PUBLISH packet, so that the
+    /// payload can be streamed from the connection instead of buffered.
+    ///
+    /// `bytes` holds the start of the packet after the fixed header and
+    /// `remaining_length` is the remaining length from the fixed header.
+    /// Returns the header together with the number of payload bytes that
+    /// follow it. A header longer than `remaining_length` results in
+    /// `Error::InvalidLength`.
+    pub fn header_only(
+        bytes: &'buf [u8],
+        flags: PacketTypeFlags,
+        remaining_length: usize,
+    ) -> Result<Status<(PublishHeader<'buf>, usize)>> {
+        let end = bytes.len().min(remaining_length);
+        let (header, read) = match PublishHeader::from_bytes(flags, &bytes[..end])? {
+            Status::Complete(parsed) => parsed,
+            Status::Partial if end < remaining_length => return Ok(Status::Partial),
+            Status::Partial => return Err(Error::InvalidLength),
+        };
+
+        Ok(Status::Complete((header, remaining_length - read)))
+    }
+
+    pub fn dup(&self) -> bool {
+        self.dup
+    }
+
+    pub fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    pub fn retain(&self) -> bool {
+        self.retain
+    }
+
+    pub fn topic(&self) -> &'buf str {
+        self.topic
+    }
+
+    /// Returns the validated topic name as raw bytes, e.g. for hashing.
+    pub fn topic_bytes(&self) -> &[u8] {
+        self.topic.as_bytes()
+    }
+
+    pub fn packet_id(&self) -> Option<PacketId> {
+        self.packet_id
+    }
+
+    pub fn payload(&self) -> &'buf [u8] {
+        self.payload
+    }
+
+    pub fn payload_len(&self) -> usize {
+        self.payload.len()
+    }
+
+    /// Returns `true` when the PUBLISH carries a zero length payload.
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    /// Returns what a broker does with its retained message for the topic
+    /// of this PUBLISH (MQTT-3.3.1-5, MQTT-3.3.1-10).
+    pub fn retained_action(&self) -> RetainedAction {
+        match (self.retain, self.payload.is_empty()) {
+            (false, _) => RetainedAction::None,
+            (true, false) => RetainedAction::Store,
+            (true, true) => RetainedAction::Clear,
+        }
+    }
+
+    /// Returns the acknowledgement the receiver of this PUBLISH sends: a
+    /// PUBACK for QoS 1, a PUBREC for QoS 2 and nothing for QoS 0.
+    pub fn ack(&self) -> Option<AckResponse> {
+        match (self.qos, self.packet_id) {
+            (QoS::AtLeastOnce, Some(id)) => Some(AckResponse::Puback(Puback::new(id))),
+            (QoS::ExactlyOnce, Some(id)) => Some(AckResponse::Pubrec(Pubrec::new(id))),
+            _ => None,
+        }
+    }
+}
+
+impl<'buf> EncodeBody for Publish<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        3 << 4 | (self.dup as u8) << 3 | self.qos.to_u8() << 1 | self.retain as u8
+    }
+
+    fn body_len(&self) -> usize {
+        2 + self.topic.len() + self.packet_id.map_or(0, |_| 2) + self.payload.len()
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        BigEndian::write_u16(buf, self.topic.len() as u16);
+        let mut write = 2;
+        buf[write..write + self.topic.len()].copy_from_slice(self.topic.as_bytes());
+        write += self.topic.len();
+
+        if let Some(id) = self.packet_id {
+            BigEndian::write_u16(&mut buf[write..], id);
+            write += 2;
+        }
+
+        buf[write..].copy_from_slice(self.payload);
+    }
+}
+
+/// The variable header of a PUBLISH packet together with the flags of its
+/// fixed header, as returned by `Publish::header_only`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct PublishHeader<'buf> {
+    dup: bool,
+    qos: QoS,
+    retain: bool,
+    topic: &'buf str,
+    packet_id: Option<PacketId>,
+}
+
+impl<'buf> PublishHeader<'buf> {
+    /// Decodes the variable header, returning it with the number of bytes
+    /// read from `bytes`.
+    fn from_bytes(
+        flags: PacketTypeFlags,
+        bytes: &'buf [u8],
+    ) -> Result<Status<(PublishHeader<'buf>, usize)>> {
+        // a PUBLISH MUST NOT have both QoS bits set (MQTT-3.3.1-4)
+        if flags & 0b0110 == 0b0110 {
+            return Err(Error::InvalidPublishQoS);
+        }
+
+        let dup = flags & 0b1000 != 0;
+        let qos = QoS::from_u8((flags & 0b0110) >> 1)?;
+        let retain = flags & 0b0001 != 0;
+
+        // DUP MUST be 0 for all QoS 0 messages (MQTT-3.3.1-2)
+        if dup && qos == QoS::AtMostOnce {
+            return Err(Error::InvalidPublishFlags);
+        }
+
+        // read topic name, which MUST NOT be empty nor contain wildcards
+        // (MQTT-4.7.3-1, MQTT-3.3.2-2)
+        let topic = complete!(parse_string(bytes));
+        validate_publish_topic(topic, ProtocolVersion::V3_1_1)?;
+        let mut read = 2 + topic.len();
+
+        // the packet identifier is only present for QoS 1 and 2
+        let packet_id = if qos != QoS::AtMostOnce {
+            let packet_id = read_u16!(bytes, read);
+            read += 2;
+            Some(packet_id)
+        } else {
+            None
+        };
+
+        Ok(Status::Complete((
+            PublishHeader {
+                dup,
+                qos,
+                retain,
+                topic,
+                packet_id,
+            },
+            read,
+        )))
+    }
+
+    pub fn dup(&self) -> bool {
+        self.dup
+    }
+
+    pub fn qos(&self) -> QoS {
+        self.qos
+    }
+
+    pub fn retain(&self) -> bool {
+        self.retain
+    }
+
+    pub fn topic(&self) -> &'buf str {
+        self.topic
+    }
+
+    pub fn packet_id(&self) -> Option<PacketId> {
+        self.packet_id
+    }
+}
+
+/// Effect of a PUBLISH on the retained message of its topic, as returned by
+/// `Publish::retained_action`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RetainedAction {
+    /// Not retained, the retained message is left as is
+    None,
+    /// Retained with a payload, which replaces the retained message
+    Store,
+    /// Retained with an empty payload, which removes the retained message
+    Clear,
+}
+
+/// The response to a QoS 1 or QoS 2 PUBLISH, as returned by
+/// `Publish::ack`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AckResponse {
+    Puback(Puback),
+    Pubrec(Pubrec),
+}
+
+impl EncodeBody for AckResponse {
+    fn fixed_header_byte(&self) -> u8 {
+        match *self {
+            AckResponse::Puback(ref packet) => packet.fixed_header_byte(),
+            AckResponse::Pubrec(ref packet) => packet.fixed_header_byte(),
+        }
+    }
+
+    fn body_len(&self) -> usize {
+        match *self {
+            AckResponse::Puback(ref packet) => packet.body_len(),
+            AckResponse::Pubrec(ref packet) => packet.body_len(),
+        }
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        match *self {
+            AckResponse::Puback(ref packet) => packet.encode_body(buf),
+            AckResponse::Pubrec(ref packet) => packet.encode_body(buf),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match *self {
+            AckResponse::Puback(ref packet) => packet.validate(),
+            AckResponse::Pubrec(ref packet) => packet.validate(),
+        }
+    }
+}
+
+/// Encodes a complete PUBLISH packet into `buf` in one go, returning the
+/// number of bytes written.
+///
+/// `packet_id` must be given for QoS 1 and 2 and omitted for QoS 0, otherwise
+/// `Error::InvalidPacketId` is returned. The topic must be a non-empty topic
+/// name without wildcards (`Error::InvalidTopicName`), and DUP must not be set
+/// for QoS 0 (`Error::InvalidPublishFlags`). A buffer too small for the
+/// packet results in `Error::InvalidLength`.
+///
+/// This is a shorthand for `Publish::new` followed by `Encode::encode`.
+pub fn encode_publish(
+    topic: &str,
+    payload: &[u8],
+    qos: QoS,
+    packet_id: Option<PacketId>,
+    retain: bool,
+    dup: bool,
+    buf: &mut [u8],
+) -> Result<usize> {
+    Publish::new(topic, payload, qos, packet_id, retain, dup)?.encode(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {parse, FixedHeader, Packet};
+
+    #[test]
+    fn qos0() {
+        let buf = [0, 3, b'a', b'/', b'b', b'h', b'i'];
+        let publish = Publish::from_bytes(0b0001, &buf).unwrap().unwrap();
+        assert!(!publish.dup());
+        assert_eq!(publish.qos(), QoS::AtMostOnce);
+        assert!(publish.retain());
+        assert_eq!(publish.topic(), "a/b");
+        assert_eq!(publish.packet_id(), None);
+        assert_eq!(publish.payload(), b"hi");
+    }
+
+    #[test]
+    fn qos1() {
+        let buf = [0, 3, b'a', b'/', b'b', 0x12, 0x34, b'h', b'i'];
+        let publish = Publish::from_bytes(0b1010, &buf).unwrap().unwrap();
+        assert!(publish.dup());
+        assert_eq!(publish.qos(), QoS::AtLeastOnce);
+        assert!(!publish.retain());
+        assert_eq!(publish.topic(), "a/b");
+        assert_eq!(publish.packet_id(), Some(0x1234));
+        assert_eq!(publish.payload(), b"hi");
+    }
+
+    #[test]
+    fn topic_bytes() {
+        let buf = [0, 3, b'a', b'/', b'b'];
+        let publish = Publish::from_bytes(0b0000, &buf).unwrap().unwrap();
+        assert_eq!(publish.topic_bytes(), publish.topic().as_bytes());
+        assert_eq!(publish.topic_bytes(), b"a/b");
+    }
+
+    #[test]
+    fn empty_payload() {
+        let buf = [0, 1, b'a', 0, 1];
+        let publish = Publish::from_bytes(0b0100, &buf).unwrap().unwrap();
+        assert_eq!(publish.qos(), QoS::ExactlyOnce);
+        assert_eq!(publish.packet_id(), Some(1));
+        assert_eq!(publish.payload(), b"");
+    }
+
+    #[test]
+    fn payload_len() {
+        let buf = [0, 1, b'a', b'h', b'i'];
+        let publish = Publish::from_bytes(0b0000, &buf).unwrap().unwrap();
+        assert_eq!(publish.payload_len(), 2);
+        assert!(!publish.is_empty());
+
+        let publish = Publish::from_bytes(0b0001, &buf[..3]).unwrap().unwrap();
+        assert_eq!(publish.payload_len(), 0);
+        assert!(publish.is_empty());
+    }
+
+    #[test]
+    fn header_only() {
+        let mut buf = [0u8; 9];
+        buf[..7].copy_from_slice(&[0, 3, b'a', b'/', b'b', 0x12, 0x34]);
+        // only the header is buffered, 1000 payload bytes are still to read
+        let (header, payload_len) = Publish::header_only(&buf[..7], 0b0010, 1007)
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.qos(), QoS::AtLeastOnce);
+        assert_eq!(header.topic(), "a/b");
+        assert_eq!(header.packet_id(), Some(0x1234));
+        assert_eq!(payload_len, 1000);
+
+        // payload bytes that are already buffered are still counted
+        let (_, payload_len) = Publish::header_only(&buf, 0b0010, 9).unwrap().unwrap();
+        assert_eq!(payload_len, 2);
+
+        for len in 0..7 {
+            let result = Publish::header_only(&buf[..len], 0b0010, 1007);
+            assert_eq!(result, Ok(Status::Partial));
+        }
+
+        let result = Publish::header_only(&buf, 0b0010, 6);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn partial() {
+        let buf = [0, 3, b'a', b'/', b'b', 0x12, 0x34];
+        for len in 0..buf.len() - 1 {
+            let result = Publish::from_bytes(0b0010, &buf[..len]).unwrap();
+            assert_eq!(result, Status::Partial);
+        }
+    }
+
+    #[test]
+    fn dup_qos0() {
+        let buf = [0, 1, b'a'];
+        let result = Publish::from_bytes(0b1000, &buf);
+        assert_eq!(result, Err(Error::InvalidPublishFlags));
+    }
+
+    #[test]
+    fn retained_action() {
+        let buf = [0, 1, b'a', b'h', b'i'];
+        let publish = Publish::from_bytes(0b0000, &buf).unwrap().unwrap();
+        assert_eq!(publish.retained_action(), RetainedAction::None);
+        let publish = Publish::from_bytes(0b0001, &buf).unwrap().unwrap();
+        assert_eq!(publish.retained_action(), RetainedAction::Store);
+        let publish = Publish::from_bytes(0b0001, &buf[..3]).unwrap().unwrap();
+        assert_eq!(publish.retained_action(), RetainedAction::Clear);
+    }
+
+    #[test]
+    fn ack() {
+        let buf = [0, 1, b'a', 0x12, 0x34];
+        let publish = Publish::from_bytes(0b0010, &buf).unwrap().unwrap();
+        let ack = publish.ack().unwrap();
+        assert_eq!(ack, AckResponse::Puback(Puback::new(0x1234)));
+        let mut out = [0u8; 4];
+        assert_eq!(ack.encode(&mut out), Ok(4));
+        assert_eq!(out, [0x40, 2, 0x12, 0x34]);
+
+        let publish = Publish::from_bytes(0b0100, &buf).unwrap().unwrap();
+        assert_eq!(
+            publish.ack(),
+            Some(AckResponse::Pubrec(Pubrec::new(0x1234)))
+        );
+
+        let publish = Publish::from_bytes(0b0000, &buf).unwrap().unwrap();
+        assert_eq!(publish.ack(), None);
+    }
+
+    #[test]
+    fn bad_topic() {
+        assert_eq!(parse(&[0x30, 3, 0, 0, b'x']), Err(Error::InvalidTopicName));
+        assert_eq!(parse(&[0x30, 3, 0, 1, b'#']), Err(Error::InvalidTopicName));
+        assert_eq!(parse(&[0x30, 3, 0, 1, b'+']), Err(Error::InvalidTopicName));
+    }
+
+    #[test]
+    fn reserved_qos() {
+        let buf = [0, 1, b'a', 0, 1];
+        let result = Publish::from_bytes(0b0110, &buf);
+        assert_eq!(result, Err(Error::InvalidPublishQoS));
+    }
+
+    #[test]
+    fn encode() {
+        let inputs = [
+            (QoS::AtMostOnce, None, false, false),
+            (QoS::AtMostOnce, None, true, false),
+            (QoS::AtLeastOnce, Some(1), false, false),
+            (QoS::AtLeastOnce, Some(0x1234), true, true),
+            (QoS::ExactlyOnce, Some(0xFFFF), false, true),
+        ];
+        for &(qos, packet_id, retain, dup) in inputs.iter() {
+            let mut buf = [0u8; 32];
+            let len =
+                encode_publish("a/b", b"hello", qos, packet_id, retain, dup, &mut buf).unwrap();
+
+            let (header, read) = FixedHeader::from_bytes(&buf).unwrap().unwrap();
+            assert_eq!(read + header.remaining_length() as usize, len);
+            let publish = Publish::from_bytes(header.flags(), &buf[read..len])
+                .unwrap()
+                .unwrap();
+            assert_eq!(publish.topic(), "a/b");
+            assert_eq!(publish.payload(), b"hello");
+            assert_eq!(publish.qos(), qos);
+            assert_eq!(publish.packet_id(), packet_id);
+            assert_eq!(publish.retain(), retain);
+            assert_eq!(publish.dup(), dup);
+        }
+    }
+
+    #[test]
+    fn new() {
+        let publish = Publish::new("a/b", b"hi", QoS::ExactlyOnce, Some(3), true, false).unwrap();
+        assert_eq!(publish.topic(), "a/b");
+        assert_eq!(publish.packet_id(), Some(3));
+        assert!(publish.retain());
+
+        let mut buf = [0u8; 16];
+        let len = publish.encode(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[0x35, 9, 0, 3, b'a', b'/', b'b', 0, 3, b'h', b'i']
+        );
+
+        let result = Publish::new("a/#", b"", QoS::AtMostOnce, None, false, false);
+        assert_eq!(result, Err(Error::InvalidTopicName));
+    }
+
+    #[test]
+    fn encode_qos1() {
+        let mut buf = [0u8; 16];
+        let len = Publish::qos1("a/b", b"hi", 7, &mut buf).unwrap();
+        match parse(&buf[..len]).unwrap().unwrap() {
+            (Packet::Publish(publish), read) => {
+                assert_eq!(read, len);
+                assert_eq!(publish.topic(), "a/b");
+                assert_eq!(publish.payload(), b"hi");
+                assert_eq!(publish.qos(), QoS::AtLeastOnce);
+                assert_eq!(publish.packet_id(), Some(7));
+                assert!(!publish.dup());
+                assert!(!publish.retain());
+            }
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+
+        let result = Publish::qos1("a/+", b"hi", 7, &mut buf);
+        assert_eq!(result, Err(Error::InvalidTopicName));
+        let result = Publish::qos1("a/b", b"hi", 0, &mut buf);
+        assert_eq!(result, Err(Error::InvalidPacketId));
+    }
+
+    #[test]
+    fn encode_invalid() {
+        let mut buf = [0u8; 32];
+        let qos0 = QoS::AtMostOnce;
+        let qos1 = QoS::AtLeastOnce;
+        let result = encode_publish("", b"", qos0, None, false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidTopicName));
+        let result = encode_publish("a/+", b"", qos0, None, false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidTopicName));
+        let result = encode_publish("a/#", b"", qos0, None, false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidTopicName));
+        let result = encode_publish("a", b"", qos0, None, false, true, &mut buf);
+        assert_eq!(result, Err(Error::InvalidPublishFlags));
+        let result = encode_publish("a", b"", qos0, Some(1), false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidPacketId));
+        let result = encode_publish("a", b"", qos1, None, false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidPacketId));
+        let result = encode_publish("a", b"", qos1, Some(0), false, false, &mut buf);
+        assert_eq!(result, Err(Error::InvalidPacketId));
+        let result = encode_publish("a", b"", qos1, Some(1), false, false, &mut buf[..6]);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use super::PacketId;
+use super::{Error, PacketType, Result};
+
+/// Tracks protocol state a stateless parser cannot check on its own.
+///
+/// Feed every packet type received on a connection to `observe`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct ConnectionState {
+    connected: bool,
+}
+
+impl ConnectionState {
+    pub fn new() -> ConnectionState {
+        ConnectionState::default()
+    }
+
+    /// Records a received packet, returning `Error::ProtocolViolation` for a
+    /// second CONNECT on the same connection (MQTT-3.1.0-2).
+    pub fn observe(&mut self, packet_type: PacketType) -> Result<()> {
+        if packet_type == PacketType::Connect {
+            if self.connected {
+                return Err(Error::ProtocolViolation);
+            }
+            self.connected = true;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+/// Tracks the packet identifiers of SUBSCRIBE and UNSUBSCRIBE packets sent by
+/// a client that have not been acknowledged yet.
+#[cfg(feature = "alloc")]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PendingRequests {
+    ids: Vec<PacketId>,
+}
+
+#[cfg(feature = "alloc")]
+impl PendingRequests {
+    pub fn new() -> PendingRequests {
+        PendingRequests::default()
+    }
+
+    /// Records a sent request awaiting acknowledgement.
+    pub fn register(&mut self, id: PacketId) {
+        self.ids.push(id);
+    }
+
+    /// Matches a received SUBACK or UNSUBACK against the outstanding
+    /// requests, returning `Error::UnexpectedAck` if `id` was never
+    /// registered or has already been acknowledged.
+    pub fn acknowledge(&mut self, id: PacketId) -> Result<()> {
+        match self.ids.iter().position(|&v| v == id) {
+            Some(index) => {
+                self.ids.swap_remove(index);
+                Ok(())
+            }
+            None => Err(Error::UnexpectedAck),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_connect() {
+        let mut state = ConnectionState::new();
+        assert!(!state.is_connected());
+        assert_eq!(state.observe(PacketType::Connect), Ok(()));
+        assert_eq!(state.observe(PacketType::Publish), Ok(()));
+        assert_eq!(state.observe(PacketType::Pingreq), Ok(()));
+        assert!(state.is_connected());
+    }
+
+    #[test]
+    fn duplicate_connect() {
+        let mut state = ConnectionState::new();
+        assert_eq!(state.observe(PacketType::Connect), Ok(()));
+        assert_eq!(
+            state.observe(PacketType::Connect),
+            Err(Error::ProtocolViolation)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn matched_ack() {
+        let mut pending = PendingRequests::new();
+        pending.register(1);
+        pending.register(2);
+        assert_eq!(pending.acknowledge(2), Ok(()));
+        assert_eq!(pending.acknowledge(1), Ok(()));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn unmatched_ack() {
+        let mut pending = PendingRequests::new();
+        pending.register(1);
+        assert_eq!(pending.acknowledge(7), Err(Error::UnexpectedAck));
+        assert_eq!(pending.acknowledge(1), Ok(()));
+        assert_eq!(pending.acknowledge(1), Err(Error::UnexpectedAck));
+    }
+}
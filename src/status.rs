@@ -54,10 +54,45 @@ macro_rules! complete {
 #[macro_export]
 macro_rules! next {
     ($bytes:ident, $read:ident) => {{
-        if $bytes.len() - $read > 0 {
+        if $read < $bytes.len() {
             $bytes[$read]
         } else {
             return Ok(Status::Partial);
         }
     }};
 }
+
+#[macro_export]
+macro_rules! read_u16 {
+    ($bytes:ident, $read:ident) => {{
+        if $bytes.len().saturating_sub($read) >= 2 {
+            <$crate::byteorder::BigEndian as $crate::byteorder::ByteOrder>::read_u16(
+                &$bytes[$read..],
+            )
+        } else {
+            return Ok(Status::Partial);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Result;
+
+    fn read_at(bytes: &[u8], read: usize) -> Result<Status<(u8, u16)>> {
+        let byte = next!(bytes, read);
+        let read = read + 1;
+        Ok(Status::Complete((byte, read_u16!(bytes, read))))
+    }
+
+    #[test]
+    fn read_past_end() {
+        let buf = [1, 0, 2];
+        assert_eq!(read_at(&buf, 0), Ok(Status::Complete((1, 2))));
+        assert_eq!(read_at(&buf, 1), Ok(Status::Partial));
+        assert_eq!(read_at(&buf, 3), Ok(Status::Partial));
+        assert_eq!(read_at(&buf, 7), Ok(Status::Partial));
+        assert_eq!(read_at(&buf[..2], 1), Ok(Status::Partial));
+    }
+}
@@ -0,0 +1,224 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::{EncodeBody, Error, PacketId, QoS, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SubackReturnCode {
+    /// Subscription accepted with the given maximum QoS
+    Success(QoS),
+    /// Subscription rejected
+    Failure,
+}
+
+impl SubackReturnCode {
+    pub fn from_u8(value: u8) -> Result<SubackReturnCode> {
+        match value {
+            0x00 => Ok(SubackReturnCode::Success(QoS::AtMostOnce)),
+            0x01 => Ok(SubackReturnCode::Success(QoS::AtLeastOnce)),
+            0x02 => Ok(SubackReturnCode::Success(QoS::ExactlyOnce)),
+            0x80 => Ok(SubackReturnCode::Failure),
+            _ => Err(Error::InvalidSubackReturnCode),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match *self {
+            SubackReturnCode::Success(qos) => qos.to_u8(),
+            SubackReturnCode::Failure => 0x80,
+        }
+    }
+
+    /// Same as `to_u8`, returning the wire byte of the return code.
+    pub fn as_u8(self) -> u8 {
+        self.to_u8()
+    }
+}
+
+/// Returns the QoS at which messages for a subscription are delivered once
+/// the server answered with `granted`, or `None` when the subscription was
+/// rejected.
+///
+/// The server may grant a lower QoS than `requested` but never a higher one,
+/// so the result is capped at `requested` should a server misbehave.
+pub fn effective_qos(requested: QoS, granted: SubackReturnCode) -> Option<QoS> {
+    match granted {
+        SubackReturnCode::Success(qos) if qos.to_u8() < requested.to_u8() => Some(qos),
+        SubackReturnCode::Success(_) => Some(requested),
+        SubackReturnCode::Failure => None,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Suback<'buf> {
+    packet_id: PacketId,
+    payload: &'buf [u8],
+}
+
+impl<'buf> Suback<'buf> {
+    /// Creates a SUBACK to be encoded with `Encode` from the return codes in
+    /// their wire format, one byte per topic filter of the acknowledged
+    /// SUBSCRIBE as given by `SubackReturnCode::to_u8`.
+    ///
+    /// A byte which is not a valid return code results in
+    /// `Error::InvalidSubackReturnCode`.
+    pub fn new(packet_id: PacketId, return_codes: &'buf [u8]) -> Result<Suback<'buf>> {
+        for &code in return_codes {
+            SubackReturnCode::from_u8(code)?;
+        }
+
+        Ok(Suback {
+            packet_id,
+            payload: return_codes,
+        })
+    }
+
+    /// Decodes the body of a SUBACK packet. Every byte after the packet
+    /// identifier is a return code.
+    pub fn from_bytes(bytes: &'buf [u8]) -> Result<Status<Suback<'buf>>> {
+        // read packet identifier
+        let read = 0;
+        let packet_id = read_u16!(bytes, read);
+
+        Ok(Status::Complete(Suback {
+            packet_id,
+            payload: &bytes[2..],
+        }))
+    }
+
+    pub fn packet_id(&self) -> PacketId {
+        self.packet_id
+    }
+
+    /// Returns an iterator over the return codes, one per topic filter of the
+    /// acknowledged SUBSCRIBE, in the same order.
+    pub fn return_codes(&self) -> SubackReturnCodes<'buf> {
+        SubackReturnCodes {
+            bytes: self.payload,
+        }
+    }
+}
+
+impl<'buf> EncodeBody for Suback<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        9 << 4
+    }
+
+    fn body_len(&self) -> usize {
+        2 + self.payload.len()
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        BigEndian::write_u16(buf, self.packet_id);
+        buf[2..].copy_from_slice(self.payload);
+    }
+}
+
+/// Iterator over the return codes of a SUBACK, decoded from the borrowed
+/// packet bytes.
+///
+/// Yields `Error::InvalidSubackReturnCode` for a byte which is neither a
+/// granted QoS nor 0x80, after which the iteration ends.
+#[derive(Clone, Debug)]
+pub struct SubackReturnCodes<'buf> {
+    bytes: &'buf [u8],
+}
+
+impl<'buf> Iterator for SubackReturnCodes<'buf> {
+    type Item = Result<SubackReturnCode>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&byte, rest) = self.bytes.split_first()?;
+        let code = SubackReturnCode::from_u8(byte);
+        self.bytes = if code.is_ok() { rest } else { &[] };
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Encode;
+
+    #[test]
+    fn return_code_round_trip() {
+        let codes = [
+            SubackReturnCode::Success(QoS::AtMostOnce),
+            SubackReturnCode::Success(QoS::AtLeastOnce),
+            SubackReturnCode::Success(QoS::ExactlyOnce),
+            SubackReturnCode::Failure,
+        ];
+        for code in codes.iter() {
+            assert_eq!(SubackReturnCode::from_u8(code.to_u8()), Ok(*code));
+            assert_eq!(SubackReturnCode::from_u8(code.as_u8()), Ok(*code));
+        }
+        assert_eq!(SubackReturnCode::Failure.to_u8(), 0x80);
+    }
+
+    #[test]
+    fn effective() {
+        let granted = SubackReturnCode::Success(QoS::AtLeastOnce);
+        assert_eq!(
+            effective_qos(QoS::ExactlyOnce, granted),
+            Some(QoS::AtLeastOnce)
+        );
+        assert_eq!(
+            effective_qos(QoS::AtMostOnce, granted),
+            Some(QoS::AtMostOnce)
+        );
+        assert_eq!(
+            effective_qos(QoS::ExactlyOnce, SubackReturnCode::Failure),
+            None
+        );
+    }
+
+    #[test]
+    fn return_codes() {
+        let buf = [0x12, 0x34, 0x00, 0x80, 0x02, 0x01];
+        let suback = Suback::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(suback.packet_id(), 0x1234);
+        let codes = suback.return_codes().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            codes,
+            [
+                SubackReturnCode::Success(QoS::AtMostOnce),
+                SubackReturnCode::Failure,
+                SubackReturnCode::Success(QoS::ExactlyOnce),
+                SubackReturnCode::Success(QoS::AtLeastOnce),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_return_code() {
+        let buf = [0, 1, 0x01, 0x03, 0x00];
+        let suback = Suback::from_bytes(&buf).unwrap().unwrap();
+        let mut codes = suback.return_codes();
+        assert_eq!(
+            codes.next(),
+            Some(Ok(SubackReturnCode::Success(QoS::AtLeastOnce)))
+        );
+        assert_eq!(codes.next(), Some(Err(Error::InvalidSubackReturnCode)));
+        assert_eq!(codes.next(), None);
+    }
+
+    #[test]
+    fn new() {
+        let codes = [
+            SubackReturnCode::Success(QoS::AtLeastOnce).to_u8(),
+            SubackReturnCode::Failure.to_u8(),
+        ];
+        let suback = Suback::new(0x1234, &codes).unwrap();
+        let mut buf = [0u8; 8];
+        let len = suback.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[0x90, 4, 0x12, 0x34, 0x01, 0x80]);
+
+        let result = Suback::new(1, &[0x00, 0x03]);
+        assert_eq!(result, Err(Error::InvalidSubackReturnCode));
+    }
+
+    #[test]
+    fn partial() {
+        assert_eq!(Suback::from_bytes(&[]).unwrap(), Status::Partial);
+        assert_eq!(Suback::from_bytes(&[0]).unwrap(), Status::Partial);
+    }
+}
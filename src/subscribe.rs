@@ -0,0 +1,568 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+use byteorder::{BigEndian, ByteOrder};
+
+#[cfg(feature = "alloc")]
+use super::encode::packet_len;
+#[cfg(feature = "alloc")]
+use super::header::encode_remaining_length;
+use super::topic::validate_topic_filter;
+use super::{parse_string, EncodeBody, Error, PacketId, QoS, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Subscribe<'buf> {
+    packet_id: PacketId,
+    payload: &'buf [u8],
+}
+
+impl<'buf> Subscribe<'buf> {
+    /// Decodes the body of a SUBSCRIBE packet, checking each topic filter
+    /// with `validate_topic_filter`. The requested QoS bytes are only checked
+    /// by `filters`.
+    pub fn from_bytes(bytes: &'buf [u8]) -> Result<Status<Subscribe<'buf>>> {
+        // read packet identifier
+        let read = 0;
+        let packet_id = read_u16!(bytes, read);
+        let payload = &bytes[2..];
+        complete!(check_filters(payload));
+
+        Ok(Status::Complete(Subscribe { packet_id, payload }))
+    }
+
+    /// Creates a SUBSCRIBE to be encoded with `Encode` from the topic filters
+    /// in their wire format, each a length prefixed string followed by the
+    /// requested QoS byte.
+    ///
+    /// A list of filters that is empty or ends inside a filter results in
+    /// `Error::InvalidLength`, and an invalid filter in the error of
+    /// `validate_topic_filter`.
+    pub fn new(packet_id: PacketId, filters: &'buf [u8]) -> Result<Subscribe<'buf>> {
+        match check_filters(filters)? {
+            Status::Complete(()) => Ok(Subscribe {
+                packet_id,
+                payload: filters,
+            }),
+            Status::Partial => Err(Error::InvalidLength),
+        }
+    }
+
+    pub fn packet_id(&self) -> PacketId {
+        self.packet_id
+    }
+
+    /// Returns an iterator over the topic filters and their requested QoS.
+    pub fn filters(&self) -> SubscribeFilters<'buf> {
+        SubscribeFilters {
+            bytes: self.payload,
+        }
+    }
+
+    /// Returns how many filters repeat a filter appearing earlier in this
+    /// SUBSCRIBE, regardless of the requested QoS.
+    ///
+    /// A repeated filter replaces the earlier subscription (MQTT-3.8.4-3), so
+    /// this is only of interest for metrics. Counting stops at the first
+    /// filter that fails to decode.
+    pub fn duplicate_filter_count(&self) -> usize {
+        let filters = || self.filters().map_while(|filter| filter.ok());
+        filters()
+            .enumerate()
+            .filter(|&(i, (filter, _))| filters().take(i).any(|(seen, _)| seen == filter))
+            .count()
+    }
+
+    /// Same as `filters` but also yields the offset at which each filter's
+    /// length prefix starts, relative to the bytes passed to `from_bytes`.
+    ///
+    /// The iteration ends at the first filter that fails to decode.
+    pub fn filters_with_offsets(&self) -> impl Iterator<Item = (usize, &'buf str, QoS)> {
+        let payload_len = self.payload.len();
+        let mut filters = self.filters();
+        core::iter::from_fn(move || {
+            let offset = 2 + payload_len - filters.bytes.len();
+            match filters.next()? {
+                Ok((filter, qos)) => Some((offset, filter, qos)),
+                Err(_) => None,
+            }
+        })
+    }
+}
+
+impl<'buf> EncodeBody for Subscribe<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        8 << 4 | 0b0010
+    }
+
+    fn body_len(&self) -> usize {
+        2 + self.payload.len()
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        BigEndian::write_u16(buf, self.packet_id);
+        buf[2..].copy_from_slice(self.payload);
+    }
+}
+
+fn check_filters(payload: &[u8]) -> Result<Status<()>> {
+    // the payload MUST contain at least one topic filter (MQTT-3.8.3-3)
+    if payload.is_empty() {
+        return Err(Error::InvalidLength);
+    }
+
+    // every filter is a length prefixed string followed by the requested QoS
+    // byte
+    let mut read = 0;
+    while read < payload.len() {
+        let filter = complete!(parse_string(&payload[read..]));
+        validate_topic_filter(filter)?;
+        read += 2 + filter.len() + 1;
+    }
+    if read > payload.len() {
+        return Ok(Status::Partial);
+    }
+
+    Ok(Status::Complete(()))
+}
+
+/// Iterator over the (topic filter, requested QoS) pairs of a SUBSCRIBE,
+/// decoded from the borrowed packet bytes.
+///
+/// Yields `Error::Utf8` for a malformed filter and `Error::InvalidQoS` for a
+/// requested QoS byte other than 0, 1 or 2 (MQTT-3.8.3-4), after which the
+/// iteration ends.
+#[derive(Clone, Debug)]
+pub struct SubscribeFilters<'buf> {
+    bytes: &'buf [u8],
+}
+
+impl<'buf> SubscribeFilters<'buf> {
+    fn next_filter(&mut self) -> Result<Option<(&'buf str, QoS)>> {
+        let bytes = self.bytes;
+        let filter = match parse_string(bytes)? {
+            Status::Complete(v) => v,
+            Status::Partial => return Ok(None),
+        };
+        let read = 2 + filter.len();
+        if read >= bytes.len() {
+            return Ok(None);
+        }
+
+        // the upper 6 bits of the requested QoS byte MUST be zero
+        let qos = QoS::from_u8(bytes[read])?;
+        self.bytes = &bytes[read + 1..];
+
+        Ok(Some((filter, qos)))
+    }
+}
+
+impl<'buf> Iterator for SubscribeFilters<'buf> {
+    type Item = Result<(&'buf str, QoS)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let filter = self.next_filter().transpose();
+        if !matches!(filter, Some(Ok(_))) {
+            self.bytes = &[];
+        }
+        filter
+    }
+}
+
+/// Splits a list of topic filters across as many SUBSCRIBE packets as needed
+/// to keep each serialized packet within `max_packet_size` bytes.
+///
+/// Packet identifiers are assigned sequentially starting at
+/// `base_packet_id`, skipping the invalid identifier zero on wrap around. A
+/// filter which does not fit into a packet of its own yields
+/// `Error::PacketTooLarge` and an invalid filter the error of
+/// `validate_topic_filter`, either of which ends the iteration.
+#[cfg(feature = "alloc")]
+pub fn split_subscribe<'a>(
+    filters: &'a [(&'a str, QoS)],
+    max_packet_size: usize,
+    base_packet_id: u16,
+) -> impl Iterator<Item = Result<Vec<u8>>> + 'a {
+    SplitSubscribe {
+        filters,
+        max_packet_size,
+        packet_id: base_packet_id,
+    }
+}
+
+#[cfg(feature = "alloc")]
+struct SplitSubscribe<'a> {
+    filters: &'a [(&'a str, QoS)],
+    max_packet_size: usize,
+    packet_id: u16,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> SplitSubscribe<'a> {
+    fn next_packet(&mut self) -> Result<Vec<u8>> {
+        // the variable header is just the packet identifier
+        let mut remaining_length = 2;
+        let mut count = 0;
+        for (filter, _) in self.filters {
+            if filter.len() > u16::MAX as usize {
+                return Err(Error::InvalidLength);
+            }
+            validate_topic_filter(filter)?;
+
+            // 2 bytes length prefix + filter + requested QoS byte
+            let len = remaining_length + 2 + filter.len() + 1;
+            if packet_len(len) > self.max_packet_size {
+                break;
+            }
+            remaining_length = len;
+            count += 1;
+        }
+
+        if count == 0 {
+            return Err(Error::PacketTooLarge);
+        }
+
+        let mut header = [0u8; 4];
+        let header_len = encode_remaining_length(remaining_length as u32, &mut header);
+
+        let mut buf = Vec::with_capacity(packet_len(remaining_length));
+        buf.push(8 << 4 | 0b0010);
+        buf.extend_from_slice(&header[..header_len]);
+
+        let mut id = [0u8; 2];
+        if self.packet_id == 0 {
+            self.packet_id = 1;
+        }
+        BigEndian::write_u16(&mut id, self.packet_id);
+        buf.extend_from_slice(&id);
+        self.packet_id = self.packet_id.wrapping_add(1);
+
+        for (filter, qos) in &self.filters[..count] {
+            BigEndian::write_u16(&mut id, filter.len() as u16);
+            buf.extend_from_slice(&id);
+            buf.extend_from_slice(filter.as_bytes());
+            buf.push(qos.to_u8());
+        }
+        self.filters = &self.filters[count..];
+
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Iterator for SplitSubscribe<'a> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.filters.is_empty() {
+            return None;
+        }
+
+        let packet = self.next_packet();
+        if packet.is_err() {
+            self.filters = &[];
+        }
+        Some(packet)
+    }
+}
+
+/// Streams a SUBSCRIBE packet with the given `filters` into `writer`,
+/// returning the number of bytes written.
+///
+/// The filters are walked twice: once to compute the remaining length for
+/// the fixed header and once to write them, so the packet never needs to be
+/// held in memory as a whole. A zero `packet_id`, an empty list of filters
+/// (MQTT-3.8.3-3), a filter longer than 65535 bytes or one failing
+/// `validate_topic_filter` results in an error of kind `InvalidInput`
+/// wrapping the corresponding `Error`.
+#[cfg(feature = "std")]
+pub fn write_subscribe<'a, W, I>(
+    writer: &mut W,
+    packet_id: PacketId,
+    filters: I,
+) -> io::Result<usize>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a str, QoS)>,
+    I::IntoIter: Clone,
+{
+    let invalid = |err: Error| io::Error::new(io::ErrorKind::InvalidInput, err);
+    if packet_id == 0 {
+        return Err(invalid(Error::InvalidPacketId));
+    }
+
+    let filters = filters.into_iter();
+    let mut remaining_length = 2;
+    let mut count = 0;
+    for (filter, _) in filters.clone() {
+        if filter.len() > u16::MAX as usize {
+            return Err(invalid(Error::InvalidLength));
+        }
+        validate_topic_filter(filter).map_err(invalid)?;
+        remaining_length += 2 + filter.len() + 1;
+        count += 1;
+    }
+
+    if count == 0 {
+        return Err(invalid(Error::InvalidLength));
+    } else if remaining_length > 268_435_455 {
+        return Err(invalid(Error::PacketTooLarge));
+    }
+
+    let mut header = [0u8; 4];
+    let header_len = encode_remaining_length(remaining_length as u32, &mut header);
+    writer.write_all(&[8 << 4 | 0b0010])?;
+    writer.write_all(&header[..header_len])?;
+
+    let mut buf = [0u8; 2];
+    BigEndian::write_u16(&mut buf, packet_id);
+    writer.write_all(&buf)?;
+    for (filter, qos) in filters {
+        BigEndian::write_u16(&mut buf, filter.len() as u16);
+        writer.write_all(&buf)?;
+        writer.write_all(filter.as_bytes())?;
+        writer.write_all(&[qos.to_u8()])?;
+    }
+
+    Ok(packet_len(remaining_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use {parse, Packet};
+    use {parse_string, Encode};
+
+    #[cfg(feature = "alloc")]
+    fn decode(buf: &[u8]) -> (u16, Vec<(&str, QoS)>) {
+        match parse(buf).unwrap().unwrap() {
+            (Packet::Subscribe(subscribe), read) if read == buf.len() => {
+                let filters = subscribe.filters().collect::<Result<_>>().unwrap();
+                (subscribe.packet_id(), filters)
+            }
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn single_filter() {
+        let buf = [0x12, 0x34, 0, 3, b'a', b'/', b'b', 1];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(subscribe.packet_id(), 0x1234);
+        let filters = subscribe.filters().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(filters, [("a/b", QoS::AtLeastOnce)]);
+    }
+
+    #[test]
+    fn multiple_filters() {
+        let buf = [
+            0, 1, 0, 1, b'a', 0, 0, 3, b'b', b'/', b'#', 2, 0, 1, b'+', 1,
+        ];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        let filters = subscribe.filters().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            filters,
+            [
+                ("a", QoS::AtMostOnce),
+                ("b/#", QoS::ExactlyOnce),
+                ("+", QoS::AtLeastOnce)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_many_filters() {
+        let names = (0..100)
+            .map(|i| format!("sensor/{}", i))
+            .collect::<Vec<_>>();
+        let filters = names.iter().map(|name| (name.as_str(), QoS::AtLeastOnce));
+
+        let mut buf = Vec::new();
+        let len = write_subscribe(&mut buf, 7, filters.clone()).unwrap();
+        assert_eq!(len, buf.len());
+
+        let subscribe = match parse(&buf).unwrap().unwrap() {
+            (Packet::Subscribe(subscribe), read) if read == len => subscribe,
+            packet => panic!("unexpected packet {:?}", packet),
+        };
+        assert_eq!(subscribe.packet_id(), 7);
+        let decoded = subscribe.filters().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(decoded, filters.collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_no_filters() {
+        let mut buf = Vec::new();
+        let err = write_subscribe(&mut buf, 1, core::iter::empty()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn write_bad_filter() {
+        let mut buf = Vec::new();
+        let filters = [("", QoS::AtMostOnce)];
+        let err = write_subscribe(&mut buf, 1, filters.iter().cloned()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn duplicate_filters() {
+        let buf = [0, 1, 0, 1, b'a', 0, 0, 1, b'b', 1, 0, 1, b'a', 2];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(subscribe.duplicate_filter_count(), 1);
+
+        let buf = [0, 1, 0, 1, b'a', 0, 0, 1, b'b', 1];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(subscribe.duplicate_filter_count(), 0);
+    }
+
+    #[test]
+    fn filter_offsets() {
+        let buf = [0, 1, 0, 3, b'a', b'/', b'b', 1, 0, 1, b'#', 0];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        let filters = subscribe.filters_with_offsets().collect::<Vec<_>>();
+        assert_eq!(
+            filters,
+            [(2, "a/b", QoS::AtLeastOnce), (8, "#", QoS::AtMostOnce)]
+        );
+        for (offset, filter, _) in filters {
+            assert_eq!(parse_string(&buf[offset..]), Ok(Status::Complete(filter)));
+        }
+    }
+
+    #[test]
+    fn truncated_filter() {
+        let buf = [0, 1, 0, 3, b'a', b'/', b'b', 1, 0, 1, b'c', 0];
+        for len in (0..buf.len()).filter(|&len| len != 2 && len != 8) {
+            let result = Subscribe::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(result, Status::Partial);
+        }
+    }
+
+    #[test]
+    fn no_filters() {
+        let result = Subscribe::from_bytes(&[0, 1]);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn new() {
+        let filters = [0, 3, b'a', b'/', b'b', 1];
+        let subscribe = Subscribe::new(0x1234, &filters).unwrap();
+        let mut buf = [0u8; 16];
+        let len = subscribe.encode(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[0x82, 8, 0x12, 0x34, 0, 3, b'a', b'/', b'b', 1]
+        );
+
+        assert_eq!(Subscribe::new(1, &[]), Err(Error::InvalidLength));
+        assert_eq!(Subscribe::new(1, &filters[..5]), Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn bad_filter() {
+        let buf = [0, 1, 0, 0, 1];
+        assert_eq!(Subscribe::from_bytes(&buf), Err(Error::InvalidTopicFilter));
+        let buf = [0, 1, 0, 1, b'a', 0, 0, 3, b'#', b'/', b'a', 1];
+        assert_eq!(Subscribe::from_bytes(&buf), Err(Error::InvalidTopicFilter));
+        let buf = [0, 1, 0, 1, 0x80, 0];
+        assert_eq!(Subscribe::from_bytes(&buf), Err(Error::Utf8));
+    }
+
+    #[test]
+    fn bad_requested_qos() {
+        let buf = [0, 1, 0, 1, b'a', 0, 0, 1, b'b', 0b0100_0001];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        let mut filters = subscribe.filters();
+        assert_eq!(filters.next(), Some(Ok(("a", QoS::AtMostOnce))));
+        assert_eq!(filters.next(), Some(Err(Error::InvalidQoS)));
+        assert_eq!(filters.next(), None);
+
+        let buf = [0, 1, 0, 1, b'a', 3];
+        let subscribe = Subscribe::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(subscribe.filters().next(), Some(Err(Error::InvalidQoS)));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn split() {
+        let filters = [
+            ("sport/tennis/+", QoS::AtMostOnce),
+            ("sport/golf/#", QoS::AtLeastOnce),
+            ("news", QoS::ExactlyOnce),
+            ("weather/+/today", QoS::AtLeastOnce),
+            ("a", QoS::AtMostOnce),
+        ];
+
+        let packets = split_subscribe(&filters, 32, 10)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(packets.len() > 1);
+
+        let mut decoded = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            assert!(packet.len() <= 32);
+            let (packet_id, packet_filters) = decode(packet);
+            assert_eq!(packet_id, 10 + i as u16);
+            assert!(!packet_filters.is_empty());
+            decoded.extend(packet_filters);
+        }
+
+        assert_eq!(decoded, filters);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn single_packet() {
+        let filters = [("a/b", QoS::AtLeastOnce), ("c/d", QoS::ExactlyOnce)];
+        let packets = split_subscribe(&filters, 1024, 1)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(decode(&packets[0]).1, filters);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn packet_id_wraps() {
+        let filters = [("a", QoS::AtMostOnce), ("b", QoS::AtMostOnce)];
+        let ids = split_subscribe(&filters, 8, 0xFFFF)
+            .map(|packet| decode(&packet.unwrap()).0)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [0xFFFF, 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn split_bad_filter() {
+        let filters = [("a", QoS::AtMostOnce), ("a/#/b", QoS::AtMostOnce)];
+        let mut packets = split_subscribe(&filters, 64, 1);
+        assert_eq!(packets.next().unwrap(), Err(Error::InvalidTopicFilter));
+        assert_eq!(packets.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn filter_too_large() {
+        let filters = [
+            ("a", QoS::AtMostOnce),
+            ("too/long/for/one", QoS::AtMostOnce),
+        ];
+        let mut packets = split_subscribe(&filters, 16, 1);
+        assert!(packets.next().unwrap().is_ok());
+        assert_eq!(packets.next().unwrap(), Err(Error::PacketTooLarge));
+        assert_eq!(packets.next(), None);
+    }
+}
@@ -0,0 +1,179 @@
+use super::{Error, ProtocolVersion, Result};
+
+/// Checks that `topic` can be used as the topic name of a PUBLISH: it must be
+/// non-empty (MQTT-4.7.3-1), fit the 2 byte length prefix (MQTT-4.7.3-3) and
+/// contain neither wildcards (MQTT-3.3.2-2) nor U+0000 (MQTT-4.7.3-2).
+///
+/// This is `validate_publish_topic` for MQTT 3.1.1.
+pub fn validate_topic_name(topic: &str) -> Result<()> {
+    validate_publish_topic(topic, ProtocolVersion::V3_1_1)
+}
+
+/// Checks `topic` against the topic name rules of a PUBLISH in `version`,
+/// returning `Error::InvalidTopicName` for any violation.
+///
+/// The rules are those of `validate_topic_name`, except that MQTT 5.0 allows
+/// an empty topic name, which is then replaced by a topic alias.
+pub fn validate_publish_topic(topic: &str, version: ProtocolVersion) -> Result<()> {
+    if topic.is_empty() && version != ProtocolVersion::V5 {
+        return Err(Error::InvalidTopicName);
+    }
+
+    if topic.len() > u16::MAX as usize || topic.contains(['+', '#', '\u{0000}']) {
+        return Err(Error::InvalidTopicName);
+    }
+
+    Ok(())
+}
+
+/// Checks that `filter` can be used as a topic filter of a SUBSCRIBE or
+/// UNSUBSCRIBE: it must be non-empty (MQTT-4.7.3-1), fit the 2 byte length
+/// prefix and not contain U+0000 (MQTT-4.7.3-2). A `#` must be the last level
+/// (MQTT-4.7.1-2) and both wildcards must occupy a whole level
+/// (MQTT-4.7.1-3).
+pub fn validate_topic_filter(filter: &str) -> Result<()> {
+    if filter.is_empty() || filter.len() > u16::MAX as usize || filter.contains('\u{0000}') {
+        return Err(Error::InvalidTopicFilter);
+    }
+
+    let mut levels = filter.split('/').peekable();
+    while let Some(level) = levels.next() {
+        let valid = match level {
+            "#" => levels.peek().is_none(),
+            "+" => true,
+            _ => !level.contains(['+', '#']),
+        };
+        if !valid {
+            return Err(Error::InvalidTopicFilter);
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns `true` when every topic name matched by the topic filter
+/// `specific` is also matched by the topic filter `general`, e.g. `a/#`
+/// subsumes `a/b/+`.
+///
+/// Both filters are assumed to be valid. As wildcards in the first level do
+/// not match topic names starting with `$` (MQTT-4.7.2-1), a filter such as
+/// `#` does not subsume `$SYS/#`.
+pub fn filter_subsumes(general: &str, specific: &str) -> bool {
+    let mut general_levels = general.split('/');
+    let mut specific_levels = specific.split('/');
+    let mut first = true;
+
+    loop {
+        match (general_levels.next(), specific_levels.next()) {
+            // "#" also matches the parent level, so it covers whatever is
+            // left in `specific`, including nothing at all
+            (Some("#"), s) => return !(first && s.is_some_and(|s| s.starts_with('$'))),
+            (Some("+"), Some(s)) => {
+                if s == "#" || (first && s.starts_with('$')) {
+                    return false;
+                }
+            }
+            (Some(g), Some(s)) => {
+                if g != s {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            (Some(_), None) | (None, Some(_)) => return false,
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_name() {
+        assert_eq!(validate_topic_name("a"), Ok(()));
+        assert_eq!(validate_topic_name("sport/tennis/player1"), Ok(()));
+        assert_eq!(validate_topic_name("/"), Ok(()));
+        assert_eq!(validate_topic_name(""), Err(Error::InvalidTopicName));
+        assert_eq!(validate_topic_name("a/+"), Err(Error::InvalidTopicName));
+        assert_eq!(validate_topic_name("#"), Err(Error::InvalidTopicName));
+        assert_eq!(
+            validate_topic_name("a\u{0000}"),
+            Err(Error::InvalidTopicName)
+        );
+    }
+
+    #[test]
+    fn publish_topic() {
+        let too_long = "a".repeat(u16::MAX as usize + 1);
+        let invalid = ["a/+", "+", "a/#", "#", "a\u{0000}", &too_long];
+        for &version in [ProtocolVersion::V3_1_1, ProtocolVersion::V5].iter() {
+            assert_eq!(validate_publish_topic("a/b", version), Ok(()));
+            assert_eq!(validate_publish_topic(&too_long[1..], version), Ok(()));
+            for topic in invalid.iter() {
+                assert_eq!(
+                    validate_publish_topic(topic, version),
+                    Err(Error::InvalidTopicName)
+                );
+            }
+        }
+
+        assert_eq!(
+            validate_publish_topic("", ProtocolVersion::V3_1_1),
+            Err(Error::InvalidTopicName)
+        );
+        assert_eq!(validate_publish_topic("", ProtocolVersion::V5), Ok(()));
+    }
+
+    #[test]
+    fn topic_filter() {
+        assert_eq!(validate_topic_filter("sport/+/score"), Ok(()));
+        assert_eq!(validate_topic_filter("sport/#"), Ok(()));
+        assert_eq!(validate_topic_filter("#"), Ok(()));
+        assert_eq!(validate_topic_filter("+/+"), Ok(()));
+        assert_eq!(validate_topic_filter("/"), Ok(()));
+        let invalid = ["", "sport/#/score", "sport#", "sport/+x", "a\u{0000}"];
+        for filter in invalid.iter() {
+            assert_eq!(
+                validate_topic_filter(filter),
+                Err(Error::InvalidTopicFilter)
+            );
+        }
+    }
+
+    #[test]
+    fn multi_level_subsumes() {
+        assert!(filter_subsumes("a/#", "a/b/c"));
+        assert!(filter_subsumes("a/#", "a/+/c"));
+        assert!(filter_subsumes("a/#", "a/#"));
+        assert!(filter_subsumes("a/#", "a"));
+        assert!(filter_subsumes("#", "a/b"));
+        assert!(!filter_subsumes("a/#", "b/c"));
+    }
+
+    #[test]
+    fn single_level_subsumes() {
+        assert!(filter_subsumes("a/+", "a/b"));
+        assert!(filter_subsumes("a/+/c", "a/+/c"));
+        assert!(!filter_subsumes("a/+", "a/b/c"));
+        assert!(!filter_subsumes("a/+", "a/#"));
+        assert!(!filter_subsumes("a/+", "a"));
+    }
+
+    #[test]
+    fn identical() {
+        assert!(filter_subsumes("a/b/c", "a/b/c"));
+        assert!(filter_subsumes("", ""));
+        assert!(!filter_subsumes("a/b/c", "a/b"));
+        assert!(!filter_subsumes("a/b", "a/b/c"));
+        assert!(!filter_subsumes("a/b", "a/+"));
+    }
+
+    #[test]
+    fn dollar_topics() {
+        assert!(!filter_subsumes("#", "$SYS/#"));
+        assert!(!filter_subsumes("+/info", "$SYS/info"));
+        assert!(filter_subsumes("$SYS/#", "$SYS/broker/+"));
+        assert!(filter_subsumes("#", "+/info"));
+    }
+}
@@ -0,0 +1,212 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use super::topic::validate_topic_filter;
+use super::{parse_string, EncodeBody, Error, PacketId, PacketTypeFlags, Result, Status};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Unsubscribe<'buf> {
+    packet_id: PacketId,
+    payload: &'buf [u8],
+}
+
+impl<'buf> Unsubscribe<'buf> {
+    /// Decodes the body of an UNSUBSCRIBE packet, checking each topic filter
+    /// with `validate_topic_filter`.
+    pub fn from_bytes(bytes: &'buf [u8]) -> Result<Status<Unsubscribe<'buf>>> {
+        // read packet identifier
+        let read = 0;
+        let packet_id = read_u16!(bytes, read);
+        let payload = &bytes[2..];
+        complete!(check_filters(payload));
+
+        Ok(Status::Complete(Unsubscribe { packet_id, payload }))
+    }
+
+    /// Creates an UNSUBSCRIBE to be encoded with `Encode` from the topic
+    /// filters in their wire format, each a length prefixed string.
+    ///
+    /// A list of filters that is empty or ends inside a filter results in
+    /// `Error::InvalidLength`, and an invalid filter in the error of
+    /// `validate_topic_filter`.
+    pub fn new(packet_id: PacketId, filters: &'buf [u8]) -> Result<Unsubscribe<'buf>> {
+        match check_filters(filters)? {
+            Status::Complete(()) => Ok(Unsubscribe {
+                packet_id,
+                payload: filters,
+            }),
+            Status::Partial => Err(Error::InvalidLength),
+        }
+    }
+
+    /// Checks the fixed header flags of an UNSUBSCRIBE, which MUST be
+    /// `0b0010` (MQTT-3.10.1-1).
+    pub fn validate_flags(flags: PacketTypeFlags) -> Result<()> {
+        if flags != 0b0010 {
+            return Err(Error::PacketFlag);
+        }
+
+        Ok(())
+    }
+
+    pub fn packet_id(&self) -> PacketId {
+        self.packet_id
+    }
+
+    /// Returns an iterator over the topic filters to unsubscribe from.
+    pub fn filters(&self) -> UnsubscribeFilters<'buf> {
+        UnsubscribeFilters {
+            bytes: self.payload,
+        }
+    }
+}
+
+impl<'buf> EncodeBody for Unsubscribe<'buf> {
+    fn fixed_header_byte(&self) -> u8 {
+        10 << 4 | 0b0010
+    }
+
+    fn body_len(&self) -> usize {
+        2 + self.payload.len()
+    }
+
+    fn encode_body(&self, buf: &mut [u8]) {
+        BigEndian::write_u16(buf, self.packet_id);
+        buf[2..].copy_from_slice(self.payload);
+    }
+}
+
+fn check_filters(payload: &[u8]) -> Result<Status<()>> {
+    // the payload MUST contain at least one topic filter (MQTT-3.10.3-2)
+    if payload.is_empty() {
+        return Err(Error::InvalidLength);
+    }
+
+    // every filter is a length prefixed string
+    let mut read = 0;
+    while read < payload.len() {
+        let filter = complete!(parse_string(&payload[read..]));
+        validate_topic_filter(filter)?;
+        read += 2 + filter.len();
+    }
+    if read > payload.len() {
+        return Ok(Status::Partial);
+    }
+
+    Ok(Status::Complete(()))
+}
+
+/// Iterator over the topic filters of an UNSUBSCRIBE, decoded from the
+/// borrowed packet bytes.
+///
+/// Yields `Error::Utf8` for a malformed filter, after which the iteration
+/// ends.
+#[derive(Clone, Debug)]
+pub struct UnsubscribeFilters<'buf> {
+    bytes: &'buf [u8],
+}
+
+impl<'buf> Iterator for UnsubscribeFilters<'buf> {
+    type Item = Result<&'buf str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.bytes;
+        match parse_string(bytes) {
+            Ok(Status::Complete(filter)) => {
+                self.bytes = &bytes[2 + filter.len()..];
+                Some(Ok(filter))
+            }
+            Ok(Status::Partial) => {
+                self.bytes = &[];
+                None
+            }
+            Err(e) => {
+                self.bytes = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {parse, Encode, Packet};
+
+    #[test]
+    fn filters() {
+        let buf = [
+            0x12, 0x34, 0, 3, b'a', b'/', b'b', 0, 1, b'#', 0, 3, b'c', b'/', b'+',
+        ];
+        let unsubscribe = Unsubscribe::from_bytes(&buf).unwrap().unwrap();
+        assert_eq!(unsubscribe.packet_id(), 0x1234);
+        let filters = unsubscribe.filters().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(filters, ["a/b", "#", "c/+"]);
+    }
+
+    #[test]
+    fn truncated_filter() {
+        let buf = [0, 1, 0, 3, b'a', b'/', b'b', 0, 1, b'c'];
+        for len in (0..buf.len()).filter(|&len| len != 2 && len != 7) {
+            let result = Unsubscribe::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(result, Status::Partial);
+        }
+    }
+
+    #[test]
+    fn no_filters() {
+        let result = Unsubscribe::from_bytes(&[0, 1]);
+        assert_eq!(result, Err(Error::InvalidLength));
+    }
+
+    #[test]
+    fn new() {
+        let filters = [0, 1, b'a', 0, 1, b'#'];
+        let unsubscribe = Unsubscribe::new(7, &filters).unwrap();
+        let mut buf = [0u8; 16];
+        let len = unsubscribe.encode(&mut buf).unwrap();
+        assert_eq!(&buf[..len], &[0xA2, 8, 0, 7, 0, 1, b'a', 0, 1, b'#']);
+
+        assert_eq!(Unsubscribe::new(1, &[]), Err(Error::InvalidLength));
+        assert_eq!(
+            Unsubscribe::new(1, &filters[..5]),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn bad_filter() {
+        let buf = [0, 1, 0, 1, b'a', 0, 1, 0x80];
+        assert_eq!(Unsubscribe::from_bytes(&buf), Err(Error::Utf8));
+        let buf = [0, 1, 0, 1, b'a', 0, 1, b'#', 0, 3, b'a', b'#', b'b'];
+        assert_eq!(
+            Unsubscribe::from_bytes(&buf),
+            Err(Error::InvalidTopicFilter)
+        );
+        assert_eq!(
+            parse(&[0xA2, 4, 0, 1, 0, 0]),
+            Err(Error::InvalidTopicFilter)
+        );
+    }
+
+    #[test]
+    fn wildcard_filter() {
+        let mut buf = [0xA2, 17, 0, 1, 0, 13, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        buf[6..].copy_from_slice(b"sport/+/score");
+        let (packet, _) = parse(&buf).unwrap().unwrap();
+        match packet {
+            Packet::Unsubscribe(unsubscribe) => {
+                let filters = unsubscribe.filters().collect::<Result<Vec<_>>>().unwrap();
+                assert_eq!(filters, ["sport/+/score"]);
+            }
+            packet => panic!("unexpected packet {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn reserved_flags() {
+        assert_eq!(Unsubscribe::validate_flags(0b0010), Ok(()));
+        for flags in [0b0000, 0b0001, 0b0011, 0b1010].iter() {
+            assert_eq!(Unsubscribe::validate_flags(*flags), Err(Error::PacketFlag));
+        }
+    }
+}